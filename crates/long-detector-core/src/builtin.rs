@@ -0,0 +1,77 @@
+//! 编译进来的龙图、奶龙模型，以及和检测有关的配置。机器人插件和命令行工具共用这一份。
+
+use crate::Thresholds;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+pub const LONG_MODEL: &[u8] = include_bytes!("../model/long.onnx");
+pub const NAILONG_MODEL: &[u8] = include_bytes!("../model/nailong.onnx");
+
+pub const LONG_LABELS: &[&str] = &["loong", "xiong"];
+pub const NAILONG_LABELS: &[&str] = &["nailong"];
+
+/// 检测器配置里决定是否命中的部分
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct DetectConfig {
+    pub trigger: f32,
+    #[serde(default)]
+    pub target_labels: Vec<String>, // 计入检测的类别，为空时只计第一个类别
+    #[serde(default)]
+    pub ignore_labels: Vec<String>, // 不计入检测的类别
+    #[serde(default)]
+    pub class_trigger: HashMap<String, f32>, // 单独设置类别的阈值，没有的用 trigger
+}
+
+impl DetectConfig {
+    /// 读取检测器的配置文件，只取检测相关的字段，不存在时用默认配置
+    pub fn from_file_or(path: &Path, default_config: Self) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(default_config);
+        }
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn thresholds(&self, labels: &[&str]) -> Thresholds {
+        Thresholds::new(
+            self.trigger,
+            self.target_labels.clone(),
+            self.ignore_labels.clone(),
+            self.class_trigger.clone(),
+            labels,
+        )
+    }
+}
+
+/// 龙图检测器默认配置
+pub fn long_detect_config() -> DetectConfig {
+    DetectConfig {
+        trigger: 0.78,
+        target_labels: vec!["loong".to_string()],
+        ignore_labels: vec!["xiong".to_string()],
+        class_trigger: HashMap::new(),
+    }
+}
+
+/// 奶龙检测器默认配置
+pub fn nailong_detect_config() -> DetectConfig {
+    DetectConfig {
+        trigger: 0.78,
+        target_labels: vec!["nailong".to_string()],
+        ignore_labels: Vec::new(),
+        class_trigger: HashMap::new(),
+    }
+}
+
+/// 内置检测器的模型、类别和默认配置，名称为 long 或 nailong
+pub fn builtin_detector(
+    name: &str,
+) -> Option<(&'static [u8], &'static [&'static str], DetectConfig)> {
+    match name {
+        "long" => Some((LONG_MODEL, LONG_LABELS, long_detect_config())),
+        "nailong" => Some((NAILONG_MODEL, NAILONG_LABELS, nailong_detect_config())),
+        _ => None,
+    }
+}
@@ -0,0 +1,341 @@
+use crate::to_rgba;
+use image::RgbaImage;
+use raqote::{DrawOptions, DrawTarget, LineJoin, PathBuilder, SolidSource, Source, StrokeStyle};
+
+/// 一张带标注的样本
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub score: f32,
+    pub positive: bool,
+}
+
+/// 某个阈值下的混淆矩阵
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Confusion {
+    pub threshold: f32,
+    pub tp: usize,
+    pub fp: usize,
+    pub tn: usize,
+    pub fn_: usize,
+}
+
+impl Confusion {
+    pub fn at(samples: &[Sample], threshold: f32) -> Self {
+        let mut res = Confusion {
+            threshold,
+            ..Default::default()
+        };
+        for v in samples {
+            match (v.score >= threshold, v.positive) {
+                (true, true) => res.tp += 1,
+                (true, false) => res.fp += 1,
+                (false, false) => res.tn += 1,
+                (false, true) => res.fn_ += 1,
+            }
+        }
+        res
+    }
+
+    /// 没有预测为正的样本时精确率记为 1
+    pub fn precision(&self) -> f32 {
+        ratio(self.tp, self.tp + self.fp, 1.0)
+    }
+
+    pub fn recall(&self) -> f32 {
+        ratio(self.tp, self.tp + self.fn_, 0.0)
+    }
+
+    pub fn false_positive_rate(&self) -> f32 {
+        ratio(self.fp, self.fp + self.tn, 0.0)
+    }
+
+    pub fn f1(&self) -> f32 {
+        let (p, r) = (self.precision(), self.recall());
+        if p + r == 0.0 {
+            0.0
+        } else {
+            2.0 * p * r / (p + r)
+        }
+    }
+}
+
+fn ratio(a: usize, b: usize, empty: f32) -> f32 {
+    if b == 0 {
+        empty
+    } else {
+        a as f32 / b as f32
+    }
+}
+
+/// 以每个出现过的分数作为阈值扫一遍，阈值从高到低
+pub fn sweep(samples: &[Sample]) -> Vec<Confusion> {
+    let mut thresholds: Vec<f32> = samples.iter().map(|v| v.score).collect();
+    thresholds.sort_by(|a, b| b.total_cmp(a));
+    thresholds.dedup();
+
+    thresholds
+        .into_iter()
+        .map(|threshold| Confusion::at(samples, threshold))
+        .collect()
+}
+
+/// 满足目标精确率时召回率最高的阈值，召回率相同时取最高的阈值，误报更少
+pub fn threshold_for_precision(curve: &[Confusion], target_precision: f32) -> Option<Confusion> {
+    curve
+        .iter()
+        .filter(|v| v.tp > 0 && v.precision() >= target_precision)
+        .max_by(|a, b| {
+            a.recall()
+                .total_cmp(&b.recall())
+                .then(a.threshold.total_cmp(&b.threshold))
+        })
+        .copied()
+}
+
+/// ROC 曲线下面积，用梯形法
+pub fn roc_auc(curve: &[Confusion]) -> f32 {
+    let mut points: Vec<(f32, f32)> = vec![(0.0, 0.0)];
+    points.extend(curve.iter().map(|v| (v.false_positive_rate(), v.recall())));
+    points.push((1.0, 1.0));
+
+    points
+        .windows(2)
+        .map(|w| (w[1].0 - w[0].0) * (w[1].1 + w[0].1) / 2.0)
+        .sum()
+}
+
+/// 左边 ROC 曲线，右边 PR 曲线，横纵轴都是 0 到 1
+pub fn render_curves(curve: &[Confusion]) -> RgbaImage {
+    const PANEL: f32 = 400.0;
+    const MARGIN: f32 = 40.0;
+    let width = (PANEL * 2.0 + MARGIN * 3.0) as i32;
+    let height = (PANEL + MARGIN * 2.0) as i32;
+
+    let mut dt = DrawTarget::new(width, height);
+    dt.clear(SolidSource {
+        r: 255,
+        g: 255,
+        b: 255,
+        a: 255,
+    });
+
+    let roc: Vec<(f32, f32)> = std::iter::once((0.0, 0.0))
+        .chain(curve.iter().map(|v| (v.false_positive_rate(), v.recall())))
+        .chain(std::iter::once((1.0, 1.0)))
+        .collect();
+    let pr: Vec<(f32, f32)> = curve.iter().map(|v| (v.recall(), v.precision())).collect();
+
+    for (i, points) in [roc, pr].iter().enumerate() {
+        let left = MARGIN + i as f32 * (PANEL + MARGIN);
+        let top = MARGIN;
+        let to_canvas = |(x, y): (f32, f32)| (left + x * PANEL, top + (1.0 - y) * PANEL);
+
+        // 网格
+        for step in 0..=10 {
+            let t = step as f32 / 10.0;
+            let (x, _) = to_canvas((t, 0.0));
+            let (_, y) = to_canvas((0.0, t));
+            stroke_line(&mut dt, (x, top), (x, top + PANEL), GRID, 1.0);
+            stroke_line(&mut dt, (left, y), (left + PANEL, y), GRID, 1.0);
+        }
+
+        // 坐标轴
+        stroke_line(&mut dt, (left, top), (left, top + PANEL), AXIS, 2.0);
+        stroke_line(
+            &mut dt,
+            (left, top + PANEL),
+            (left + PANEL, top + PANEL),
+            AXIS,
+            2.0,
+        );
+
+        let mut pb = PathBuilder::new();
+        for (j, &point) in points.iter().enumerate() {
+            let (x, y) = to_canvas(point);
+            if j == 0 {
+                pb.move_to(x, y);
+            } else {
+                pb.line_to(x, y);
+            }
+        }
+        stroke_path(&mut dt, pb, CURVE, 3.0);
+    }
+
+    to_rgba(&dt)
+}
+
+const GRID: SolidSource = SolidSource {
+    r: 225,
+    g: 225,
+    b: 225,
+    a: 255,
+};
+const AXIS: SolidSource = SolidSource {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 255,
+};
+const CURVE: SolidSource = SolidSource {
+    r: 220,
+    g: 40,
+    b: 40,
+    a: 255,
+};
+
+fn stroke_line(
+    dt: &mut DrawTarget,
+    from: (f32, f32),
+    to: (f32, f32),
+    color: SolidSource,
+    width: f32,
+) {
+    let mut pb = PathBuilder::new();
+    pb.move_to(from.0, from.1);
+    pb.line_to(to.0, to.1);
+    stroke_path(dt, pb, color, width);
+}
+
+fn stroke_path(dt: &mut DrawTarget, pb: PathBuilder, color: SolidSource, width: f32) {
+    dt.stroke(
+        &pb.finish(),
+        &Source::Solid(color),
+        &StrokeStyle {
+            join: LineJoin::Round,
+            width,
+            ..StrokeStyle::default()
+        },
+        &DrawOptions::new(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 分数从高到低: 正 0.9、正 0.8、负 0.7、正 0.6、负 0.4
+    fn samples() -> Vec<Sample> {
+        [
+            (0.9, true),
+            (0.8, true),
+            (0.7, false),
+            (0.6, true),
+            (0.4, false),
+        ]
+        .into_iter()
+        .map(|(score, positive)| Sample { score, positive })
+        .collect()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn confusion_at_threshold() {
+        let v = Confusion::at(&samples(), 0.7);
+        assert_eq!((v.tp, v.fp, v.tn, v.fn_), (2, 1, 1, 1));
+        assert!(close(v.precision(), 2.0 / 3.0));
+        assert!(close(v.recall(), 2.0 / 3.0));
+        assert!(close(v.false_positive_rate(), 0.5));
+        assert!(close(v.f1(), 2.0 / 3.0));
+    }
+
+    #[test]
+    fn confusion_without_predictions() {
+        let v = Confusion::at(&samples(), 1.0);
+        assert_eq!((v.tp, v.fp, v.tn, v.fn_), (0, 0, 2, 3));
+        assert!(close(v.precision(), 1.0));
+        assert!(close(v.recall(), 0.0));
+        assert!(close(v.f1(), 0.0));
+    }
+
+    #[test]
+    fn sweep_thresholds_descending() {
+        let curve = sweep(&samples());
+        let thresholds: Vec<f32> = curve.iter().map(|v| v.threshold).collect();
+        assert_eq!(thresholds, vec![0.9, 0.8, 0.7, 0.6, 0.4]);
+        let tp_fp: Vec<_> = curve.iter().map(|v| (v.tp, v.fp)).collect();
+        assert_eq!(tp_fp, vec![(1, 0), (2, 0), (2, 1), (3, 1), (3, 2)]);
+    }
+
+    #[test]
+    fn sweep_dedups_scores() {
+        let samples = [
+            Sample {
+                score: 0.5,
+                positive: true,
+            },
+            Sample {
+                score: 0.5,
+                positive: false,
+            },
+        ];
+        assert_eq!(sweep(&samples).len(), 1);
+    }
+
+    #[test]
+    fn roc_auc_by_hand() {
+        // 6 对正负样本里有 5 对正样本分数更高
+        assert!(close(roc_auc(&sweep(&samples())), 5.0 / 6.0));
+    }
+
+    #[test]
+    fn roc_auc_perfect_and_random() {
+        let perfect = [
+            Sample {
+                score: 0.9,
+                positive: true,
+            },
+            Sample {
+                score: 0.1,
+                positive: false,
+            },
+        ];
+        assert!(close(roc_auc(&sweep(&perfect)), 1.0));
+
+        let same = [
+            Sample {
+                score: 0.5,
+                positive: true,
+            },
+            Sample {
+                score: 0.5,
+                positive: false,
+            },
+        ];
+        assert!(close(roc_auc(&sweep(&same)), 0.5));
+    }
+
+    #[test]
+    fn threshold_for_precision_max_recall() {
+        let curve = sweep(&samples());
+        // 精确率: 0.9 -> 1, 0.8 -> 1, 0.7 -> 2/3, 0.6 -> 3/4, 0.4 -> 3/5
+        let v = threshold_for_precision(&curve, 0.7).unwrap();
+        assert!(close(v.threshold, 0.6));
+        let v = threshold_for_precision(&curve, 0.9).unwrap();
+        assert!(close(v.threshold, 0.8));
+    }
+
+    #[test]
+    fn threshold_for_precision_tie_prefers_highest() {
+        // 0.6 和 0.4 的召回率都是 1
+        let v = threshold_for_precision(&sweep(&samples()), 0.6).unwrap();
+        assert!(close(v.threshold, 0.6));
+    }
+
+    #[test]
+    fn threshold_for_precision_unreachable() {
+        let samples = [
+            Sample {
+                score: 0.9,
+                positive: false,
+            },
+            Sample {
+                score: 0.5,
+                positive: true,
+            },
+        ];
+        assert!(threshold_for_precision(&sweep(&samples), 0.9).is_none());
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// 路径本身是文件时只有它，是文件夹时递归找出里面的所有文件，按路径排序。
+/// 符号链接指向的文件夹按真实路径去重，不会因为链接成环一直找下去
+pub fn collect_files(path: &Path, mut on_error: impl FnMut(&Path, std::io::Error)) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    walk(path, &mut files, &mut visited, &mut on_error);
+    files.sort();
+    files
+}
+
+fn walk(
+    path: &Path,
+    files: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+    on_error: &mut impl FnMut(&Path, std::io::Error),
+) {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return;
+    }
+
+    match path.canonicalize() {
+        Ok(real) => {
+            if !visited.insert(real) {
+                return;
+            }
+        }
+        Err(err) => return on_error(path, err),
+    }
+    let entries = match std::fs::read_dir(path) {
+        Ok(v) => v,
+        Err(err) => return on_error(path, err),
+    };
+    for entry in entries.flatten() {
+        walk(&entry.path(), files, visited, on_error);
+    }
+}
@@ -0,0 +1,499 @@
+use image::codecs::gif::GifDecoder;
+use image::AnimationDecoder;
+use image::{imageops::FilterType, GenericImageView};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use ndarray::{s, Array, Axis};
+use ort::GraphOptimizationLevel;
+use ort::{inputs, Session, SessionOutputs};
+use raqote::{DrawOptions, DrawTarget, LineJoin, PathBuilder, SolidSource, Source, StrokeStyle};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+/// 模型输入尺寸
+const INPUT_SIZE: u32 = 640;
+/// 低于这个分数的框直接丢弃
+const MIN_SCORE: f32 = 0.3;
+/// NMS 的 IoU 阈值
+const NMS_IOU: f32 = 0.7;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BoundingBox {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+}
+
+/// 哪些类别算命中，以及各自的阈值
+#[derive(Debug, Clone)]
+pub struct Thresholds {
+    pub trigger: f32,
+    pub target_labels: Vec<String>,
+    pub ignore_labels: Vec<String>,
+    pub class_trigger: HashMap<String, f32>,
+}
+
+impl Thresholds {
+    /// target_labels 为空时只计模型的第一个类别
+    pub fn new(
+        trigger: f32,
+        target_labels: Vec<String>,
+        ignore_labels: Vec<String>,
+        class_trigger: HashMap<String, f32>,
+        labels: &[&str],
+    ) -> Self {
+        let target_labels = if target_labels.is_empty() {
+            labels.iter().take(1).map(|v| v.to_string()).collect()
+        } else {
+            target_labels
+        };
+
+        Self {
+            trigger,
+            target_labels,
+            ignore_labels,
+            class_trigger,
+        }
+    }
+
+    pub fn is_target(&self, label: &str) -> bool {
+        !self.ignore_labels.iter().any(|v| v == label)
+            && self.target_labels.iter().any(|v| v == label)
+    }
+
+    pub fn trigger_of(&self, label: &str) -> f32 {
+        self.class_trigger
+            .get(label)
+            .copied()
+            .unwrap_or(self.trigger)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectedBox {
+    pub bbox: BoundingBox,
+    pub label: &'static str,
+    pub score: f32,
+    pub is_target: bool,
+    pub trigger: f32,
+}
+
+impl DetectedBox {
+    pub fn is_hit(&self) -> bool {
+        self.is_target && self.score >= self.trigger
+    }
+}
+
+/// 一张图的检测结果，框按分数从高到低排列
+#[derive(Debug, Clone, Serialize)]
+pub struct Detection {
+    pub boxes: Vec<DetectedBox>,
+    pub max_target_score: f32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Detection {
+    /// 超过阈值的目标框中分数最高的一个
+    pub fn hit(&self) -> Option<&DetectedBox> {
+        self.boxes.iter().find(|v| v.is_hit())
+    }
+}
+
+pub struct Model {
+    session: Session,
+    pub labels: Vec<&'static str>,
+}
+
+impl Model {
+    pub fn new(model_bytes: &[u8], labels: Vec<&'static str>) -> ort::Result<Self> {
+        let session = Session::builder()?
+            .with_optimization_level(GraphOptimizationLevel::Level3)?
+            .with_intra_threads(4)?
+            .commit_from_memory(model_bytes)?;
+
+        Ok(Self { session, labels })
+    }
+
+    pub fn from_file(
+        path: impl AsRef<std::path::Path>,
+        labels: Vec<&'static str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let model_bytes = std::fs::read(path)?;
+        Ok(Self::new(&model_bytes, labels)?)
+    }
+
+    /// 推理并做 NMS，结果按 thresholds 标记目标类别
+    pub fn detect(
+        &self,
+        original_img: &DynamicImage,
+        thresholds: &Thresholds,
+    ) -> ort::Result<Detection> {
+        let (img_width, img_height) = (original_img.width(), original_img.height());
+        let img = original_img.resize_exact(INPUT_SIZE, INPUT_SIZE, FilterType::CatmullRom);
+
+        let size = INPUT_SIZE as usize;
+        let mut input = Array::zeros((1, 3, size, size));
+        for pixel in img.pixels() {
+            let x = pixel.0 as _;
+            let y = pixel.1 as _;
+            let [r, g, b, _] = pixel.2 .0;
+            input[[0, 0, y, x]] = (r as f32) / 255.;
+            input[[0, 1, y, x]] = (g as f32) / 255.;
+            input[[0, 2, y, x]] = (b as f32) / 255.;
+        }
+
+        let outputs: SessionOutputs = self.session.run(inputs!["images" => input.view()]?)?;
+        let output = outputs["output0"]
+            .try_extract_tensor::<f32>()?
+            .t()
+            .into_owned();
+
+        let scale_x = img_width as f32 / INPUT_SIZE as f32;
+        let scale_y = img_height as f32 / INPUT_SIZE as f32;
+
+        let mut boxes = Vec::new();
+        let output = output.slice(s![.., .., 0]);
+        for row in output.axis_iter(Axis(0)) {
+            let row: Vec<_> = row.iter().copied().collect();
+            let (class_id, prob) = row
+                .iter()
+                .skip(4)
+                .enumerate()
+                .map(|(index, value)| (index, *value))
+                .reduce(|accum, row| if row.1 > accum.1 { row } else { accum })
+                .unwrap();
+
+            if prob < MIN_SCORE {
+                continue;
+            }
+
+            let label = self.labels[class_id];
+            let xc = row[0] * scale_x;
+            let yc = row[1] * scale_y;
+            let w = row[2] * scale_x;
+            let h = row[3] * scale_y;
+            boxes.push(DetectedBox {
+                bbox: BoundingBox {
+                    x1: xc - w / 2.,
+                    y1: yc - h / 2.,
+                    x2: xc + w / 2.,
+                    y2: yc + h / 2.,
+                },
+                label,
+                score: prob,
+                is_target: thresholds.is_target(label),
+                trigger: thresholds.trigger_of(label),
+            });
+        }
+
+        let boxes = nms(boxes);
+        let max_target_score = boxes
+            .iter()
+            .filter(|v| v.is_target)
+            .map(|v| v.score)
+            .fold(0.0, f32::max);
+
+        Ok(Detection {
+            boxes,
+            max_target_score,
+            width: img_width,
+            height: img_height,
+        })
+    }
+}
+
+/// 按类别分别做 NMS，不同类别的框不会互相抑制
+fn nms(mut boxes: Vec<DetectedBox>) -> Vec<DetectedBox> {
+    boxes.sort_by(|box1, box2| box2.score.total_cmp(&box1.score));
+    let mut result = Vec::new();
+
+    while !boxes.is_empty() {
+        let best = boxes.remove(0);
+        boxes.retain(|v| {
+            v.label != best.label
+                || intersection(&best.bbox, &v.bbox) / union(&best.bbox, &v.bbox) < NMS_IOU
+        });
+        result.push(best);
+    }
+
+    result
+}
+
+/// 在原图上画出目标类别的框
+pub fn annotate(original_img: &DynamicImage, detection: &Detection) -> RgbaImage {
+    let (img_width, img_height) = (detection.width, detection.height);
+    let mut dt = DrawTarget::new(img_width as _, img_height as _);
+
+    for v in detection.boxes.iter().filter(|v| v.is_target) {
+        let bbox = v.bbox;
+        let mut pb = PathBuilder::new();
+        pb.rect(bbox.x1, bbox.y1, bbox.x2 - bbox.x1, bbox.y2 - bbox.y1);
+        let path = pb.finish();
+
+        let color = SolidSource {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+
+        dt.stroke(
+            &path,
+            &Source::Solid(color),
+            &StrokeStyle {
+                join: LineJoin::Round,
+                width: 4.,
+                ..StrokeStyle::default()
+            },
+            &DrawOptions::new(),
+        );
+    }
+
+    // 没画框的地方用原图
+    let mut res_img = to_rgba(&dt);
+    for (x, y, pixel) in res_img.enumerate_pixels_mut() {
+        if pixel.0[3] == 0 {
+            let original_pixel = original_img.get_pixel(x, y);
+            *pixel = image::Rgba([
+                original_pixel.0[0],
+                original_pixel.0[1],
+                original_pixel.0[2],
+                255,
+            ]);
+        }
+    }
+
+    res_img
+}
+
+/// 把 raqote 画好的图转成 RgbaImage，raqote 的像素是预乘过的 ARGB，这里除回 alpha
+pub fn to_rgba(dt: &DrawTarget) -> RgbaImage {
+    let data = dt
+        .get_data()
+        .iter()
+        .flat_map(|&p| {
+            let a = (p >> 24) & 0xff;
+            let unpremultiply = |c: u32| match a {
+                0 | 255 => c as u8,
+                _ => ((c * 255 + a / 2) / a).min(255) as u8,
+            };
+            [
+                unpremultiply((p >> 16) & 0xff),
+                unpremultiply((p >> 8) & 0xff),
+                unpremultiply(p & 0xff),
+                a as u8,
+            ]
+        })
+        .collect();
+
+    RgbaImage::from_raw(dt.width() as u32, dt.height() as u32, data).unwrap()
+}
+
+/// 解码图片，gif 取第一帧
+pub fn decode_image(
+    data: &[u8],
+    format: ImageFormat,
+) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    match format {
+        ImageFormat::Gif => extract_frame_from_gif_bytes(data, 0),
+        _ => Ok(image::load_from_memory(data)?),
+    }
+}
+
+/// 自动识别格式并解码
+pub fn decode_bytes(data: &[u8]) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let format = image::guess_format(data)?;
+    decode_image(data, format)
+}
+
+pub fn extract_frame_from_gif_bytes(
+    data: &[u8],
+    frame_index: usize,
+) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let cursor = Cursor::new(data);
+    let decoder = GifDecoder::new(cursor)?;
+    let frames = decoder.into_frames().collect_frames()?;
+
+    if frame_index >= frames.len() {
+        return Err("Frame index out of bounds".into());
+    }
+
+    let frame = frames[frame_index].clone();
+    let dynamic_image = DynamicImage::ImageRgba8(frame.into_buffer());
+
+    Ok(dynamic_image)
+}
+
+/// 两个框重叠部分的面积，不重叠时为 0
+pub fn intersection(box1: &BoundingBox, box2: &BoundingBox) -> f32 {
+    let w = (box1.x2.min(box2.x2) - box1.x1.max(box2.x1)).max(0.0);
+    let h = (box1.y2.min(box2.y2) - box1.y1.max(box2.y1)).max(0.0);
+    w * h
+}
+
+pub fn union(box1: &BoundingBox, box2: &BoundingBox) -> f32 {
+    ((box1.x2 - box1.x1) * (box1.y2 - box1.y1)) + ((box2.x2 - box2.x1) * (box2.y2 - box2.y1))
+        - intersection(box1, box2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(x1: f32, y1: f32, x2: f32, y2: f32) -> BoundingBox {
+        BoundingBox { x1, y1, x2, y2 }
+    }
+
+    fn detected(label: &'static str, score: f32, bbox: BoundingBox) -> DetectedBox {
+        let thresholds = thresholds();
+        DetectedBox {
+            bbox,
+            label,
+            score,
+            is_target: thresholds.is_target(label),
+            trigger: thresholds.trigger_of(label),
+        }
+    }
+
+    fn thresholds() -> Thresholds {
+        Thresholds::new(
+            0.78,
+            vec!["loong".to_string()],
+            vec!["xiong".to_string()],
+            HashMap::from([("loong".to_string(), 0.5)]),
+            &["loong", "xiong"],
+        )
+    }
+
+    #[test]
+    fn intersection_of_separate_boxes_is_zero() {
+        let a = bbox(0.0, 0.0, 10.0, 10.0);
+        let b = bbox(20.0, 20.0, 30.0, 30.0);
+        assert_eq!(intersection(&a, &b), 0.0);
+        assert_eq!(union(&a, &b), 200.0);
+
+        // 只在一个方向上重叠
+        let c = bbox(5.0, 20.0, 15.0, 30.0);
+        assert_eq!(intersection(&a, &c), 0.0);
+    }
+
+    #[test]
+    fn intersection_of_overlapping_boxes() {
+        let a = bbox(0.0, 0.0, 10.0, 10.0);
+        let b = bbox(5.0, 5.0, 15.0, 15.0);
+        assert_eq!(intersection(&a, &b), 25.0);
+        assert_eq!(union(&a, &b), 175.0);
+    }
+
+    #[test]
+    fn nms_suppresses_overlapping_boxes_of_same_class() {
+        let boxes = vec![
+            detected("loong", 0.6, bbox(0.0, 0.0, 10.0, 10.0)),
+            detected("loong", 0.9, bbox(0.0, 0.0, 10.0, 9.0)),
+            detected("loong", 0.7, bbox(50.0, 50.0, 60.0, 60.0)),
+        ];
+        let result = nms(boxes);
+        let scores: Vec<f32> = result.iter().map(|v| v.score).collect();
+        assert_eq!(scores, vec![0.9, 0.7]);
+    }
+
+    #[test]
+    fn nms_keeps_overlapping_boxes_of_other_classes() {
+        let boxes = vec![
+            detected("xiong", 0.95, bbox(0.0, 0.0, 10.0, 10.0)),
+            detected("loong", 0.8, bbox(0.0, 0.0, 10.0, 10.0)),
+        ];
+        let result = nms(boxes);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].label, "xiong");
+        assert_eq!(result[1].label, "loong");
+    }
+
+    #[test]
+    fn nms_keeps_separate_boxes() {
+        let boxes = vec![
+            detected("loong", 0.9, bbox(0.0, 0.0, 10.0, 10.0)),
+            detected("loong", 0.8, bbox(20.0, 20.0, 30.0, 30.0)),
+        ];
+        assert_eq!(nms(boxes).len(), 2);
+    }
+
+    #[test]
+    fn is_target_respects_target_and_ignore_labels() {
+        let thresholds = thresholds();
+        assert!(thresholds.is_target("loong"));
+        assert!(!thresholds.is_target("xiong"));
+        assert!(!thresholds.is_target("other"));
+
+        // 没有配置目标类别时只计第一个类别
+        let thresholds = Thresholds::new(0.78, vec![], vec![], HashMap::new(), &["a", "b"]);
+        assert!(thresholds.is_target("a"));
+        assert!(!thresholds.is_target("b"));
+
+        // 同时在目标和忽略里时忽略优先
+        let thresholds = Thresholds::new(
+            0.78,
+            vec!["a".to_string()],
+            vec!["a".to_string()],
+            HashMap::new(),
+            &["a"],
+        );
+        assert!(!thresholds.is_target("a"));
+    }
+
+    #[test]
+    fn trigger_of_uses_class_trigger() {
+        let thresholds = thresholds();
+        assert_eq!(thresholds.trigger_of("loong"), 0.5);
+        assert_eq!(thresholds.trigger_of("xiong"), 0.78);
+    }
+
+    #[test]
+    fn hit_is_highest_target_box_over_trigger() {
+        let detection = Detection {
+            boxes: vec![
+                detected("xiong", 0.95, bbox(0.0, 0.0, 10.0, 10.0)),
+                detected("loong", 0.6, bbox(0.0, 0.0, 10.0, 10.0)),
+                detected("loong", 0.55, bbox(20.0, 20.0, 30.0, 30.0)),
+            ],
+            max_target_score: 0.6,
+            width: 100,
+            height: 100,
+        };
+        let hit = detection.hit().unwrap();
+        assert_eq!(hit.label, "loong");
+        assert_eq!(hit.score, 0.6);
+    }
+
+    #[test]
+    fn no_hit_below_trigger() {
+        let detection = Detection {
+            boxes: vec![
+                detected("xiong", 0.95, bbox(0.0, 0.0, 10.0, 10.0)),
+                detected("loong", 0.4, bbox(0.0, 0.0, 10.0, 10.0)),
+            ],
+            max_target_score: 0.4,
+            width: 100,
+            height: 100,
+        };
+        assert!(detection.hit().is_none());
+    }
+
+    #[test]
+    fn to_rgba_unpremultiplies() {
+        let mut dt = DrawTarget::new(4, 1);
+        dt.get_data_mut().copy_from_slice(&[
+            0xff_10_20_30, // 不透明
+            0x80_40_20_00, // 半透明，预乘过
+            0x00_00_00_00, // 全透明
+            0x01_01_01_01, // 误差不会超过 255
+        ]);
+        let img = to_rgba(&dt);
+        assert_eq!(img.get_pixel(0, 0).0, [0x10, 0x20, 0x30, 0xff]);
+        assert_eq!(img.get_pixel(1, 0).0, [0x80, 0x40, 0x00, 0x80]);
+        assert_eq!(img.get_pixel(2, 0).0, [0, 0, 0, 0]);
+        assert_eq!(img.get_pixel(3, 0).0, [255, 255, 255, 1]);
+    }
+}
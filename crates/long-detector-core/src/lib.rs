@@ -0,0 +1,23 @@
+//! 龙图、奶龙检测的核心部分，不依赖 kovi，可以单独用来加载模型和给图片打分。
+//!
+//! ```no_run
+//! use long_detector_core::{decode_bytes, Model, Thresholds};
+//!
+//! let model = Model::from_file("long.onnx", vec!["loong", "xiong"]).unwrap();
+//! let thresholds = Thresholds::new(0.78, vec![], vec![], Default::default(), &model.labels);
+//!
+//! let img = decode_bytes(&std::fs::read("test.png").unwrap()).unwrap();
+//! let detection = model.detect(&img, &thresholds).unwrap();
+//! println!("{:?}", detection.hit());
+//! ```
+
+#[cfg(feature = "builtin")]
+pub mod builtin;
+pub mod eval;
+pub mod files;
+pub mod inference;
+
+pub use inference::{
+    annotate, decode_bytes, decode_image, to_rgba, BoundingBox, DetectedBox, Detection, Model,
+    Thresholds,
+};
@@ -0,0 +1,85 @@
+use long_detector_core::Detection;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+/// 图片原始数据的哈希和长度
+pub(crate) type CacheKey = (u64, usize);
+
+/// 同一张图片（例如被反复转发的表情）的检测结果，满了丢掉最早放进来的
+pub(crate) struct DetectionCache {
+    capacity: usize,
+    inner: Mutex<(HashMap<CacheKey, Detection>, VecDeque<CacheKey>)>,
+}
+
+impl DetectionCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new((HashMap::new(), VecDeque::new())),
+        }
+    }
+
+    /// 只在本进程内使用，不需要跨版本稳定
+    pub(crate) fn key(img_data: &[u8]) -> CacheKey {
+        let mut hasher = DefaultHasher::new();
+        img_data.hash(&mut hasher);
+        (hasher.finish(), img_data.len())
+    }
+
+    pub(crate) fn get(&self, key: CacheKey) -> Option<Detection> {
+        self.inner.lock().unwrap().0.get(&key).cloned()
+    }
+
+    pub(crate) fn insert(&self, key: CacheKey, detection: Detection) {
+        let mut inner = self.inner.lock().unwrap();
+        let (map, order) = &mut *inner;
+        if map.insert(key, detection).is_some() {
+            return;
+        }
+        order.push_back(key);
+        while order.len() > self.capacity {
+            if let Some(oldest) = order.pop_front() {
+                map.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(score: f32) -> Detection {
+        Detection {
+            boxes: Vec::new(),
+            max_target_score: score,
+            width: 1,
+            height: 1,
+        }
+    }
+
+    #[test]
+    fn evicts_oldest_first() {
+        let cache = DetectionCache::new(2);
+        let (a, b, c) = (
+            DetectionCache::key(b"a"),
+            DetectionCache::key(b"b"),
+            DetectionCache::key(b"c"),
+        );
+        cache.insert(a, detection(0.1));
+        cache.insert(b, detection(0.2));
+        cache.insert(c, detection(0.3));
+
+        assert!(cache.get(a).is_none());
+        assert_eq!(cache.get(b).unwrap().max_target_score, 0.2);
+        assert_eq!(cache.get(c).unwrap().max_target_score, 0.3);
+    }
+
+    #[test]
+    fn same_bytes_same_key() {
+        assert_eq!(DetectionCache::key(b"img"), DetectionCache::key(b"img"));
+        assert_ne!(DetectionCache::key(b"img"), DetectionCache::key(b"img2"));
+    }
+}
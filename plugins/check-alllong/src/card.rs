@@ -0,0 +1,230 @@
+use ab_glyph::{point, Font, FontVec, GlyphId, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+use kovi::log::{error, info};
+use long_detector_core::to_rgba;
+use raqote::{DrawOptions, DrawTarget, SolidSource, Source};
+use serde::{Deserialize, Serialize};
+
+const WIDTH: i32 = 480;
+const PADDING: f32 = 24.0;
+const TITLE_SIZE: f32 = 28.0;
+const TEXT_SIZE: f32 = 20.0;
+const LINE_HEIGHT: f32 = 32.0;
+const CHART_HEIGHT: f32 = 120.0;
+
+const BACKGROUND: SolidSource = SolidSource {
+    r: 250,
+    g: 247,
+    b: 240,
+    a: 255,
+};
+const HEADER: SolidSource = SolidSource {
+    r: 220,
+    g: 60,
+    b: 50,
+    a: 255,
+};
+const BAR: SolidSource = SolidSource {
+    r: 240,
+    g: 150,
+    b: 60,
+    a: 255,
+};
+const BAR_EMPTY: SolidSource = SolidSource {
+    r: 225,
+    g: 220,
+    b: 210,
+    a: 255,
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct CardConfig {
+    pub(crate) enabled: bool,     // 用图片卡片代替文字回复
+    pub(crate) font_path: String, // 支持中文的字体文件，ttf、otf 或 ttc
+}
+
+impl Default for CardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            font_path: "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc".to_string(),
+        }
+    }
+}
+
+/// 统计卡片，标题下面是几行文字和每天次数的柱状图
+pub(crate) struct Card {
+    pub(crate) title: String,
+    pub(crate) lines: Vec<String>,
+    pub(crate) daily: Vec<u64>,     // 从早到晚每天的次数，最后一个是今天
+    pub(crate) chart_label: String, // 柱状图下面的说明
+}
+
+pub(crate) struct CardRenderer {
+    font: FontVec,
+}
+
+impl CardRenderer {
+    /// 未开启或字体读取失败时返回 None，此时使用文字回复
+    pub(crate) fn load(config: &CardConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let data = match std::fs::read(&config.font_path) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("读取字体 {} 失败: {}，改用文字回复", config.font_path, err);
+                return None;
+            }
+        };
+        match FontVec::try_from_vec(data) {
+            Ok(font) => {
+                info!("统计卡片已开启，字体 {}", config.font_path);
+                Some(Self { font })
+            }
+            Err(err) => {
+                error!("解析字体 {} 失败: {}，改用文字回复", config.font_path, err);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn render(&self, card: &Card) -> RgbaImage {
+        let header_height = PADDING * 2.0 + TITLE_SIZE;
+        let lines_top = header_height + PADDING;
+        let chart_top = lines_top + card.lines.len() as f32 * LINE_HEIGHT + PADDING;
+        let height = if card.daily.is_empty() {
+            chart_top
+        } else {
+            chart_top + CHART_HEIGHT + TEXT_SIZE + PADDING * 2.0
+        };
+
+        let mut dt = DrawTarget::new(WIDTH, height as i32);
+        dt.clear(BACKGROUND);
+        dt.fill_rect(
+            0.0,
+            0.0,
+            WIDTH as f32,
+            header_height,
+            &Source::Solid(HEADER),
+            &DrawOptions::new(),
+        );
+
+        // 柱状图，没有命中的日子画一条细线
+        let chart_width = WIDTH as f32 - PADDING * 2.0;
+        let max = card.daily.iter().copied().max().unwrap_or(0).max(1);
+        let slot = chart_width / card.daily.len().max(1) as f32;
+        for (i, &v) in card.daily.iter().enumerate() {
+            let (color, bar_height) = if v == 0 {
+                (BAR_EMPTY, 2.0)
+            } else {
+                (BAR, (v as f32 / max as f32 * CHART_HEIGHT).max(2.0))
+            };
+            dt.fill_rect(
+                PADDING + i as f32 * slot + slot * 0.15,
+                chart_top + CHART_HEIGHT - bar_height,
+                slot * 0.7,
+                bar_height,
+                &Source::Solid(color),
+                &DrawOptions::new(),
+            );
+        }
+
+        let mut img = to_rgba(&dt);
+        let white = Rgba([255, 255, 255, 255]);
+        let black = Rgba([40, 40, 40, 255]);
+        let gray = Rgba([130, 130, 130, 255]);
+
+        let title = self.fit(&card.title, TITLE_SIZE);
+        self.draw_text(&mut img, &title, PADDING, PADDING, TITLE_SIZE, white);
+        for (i, line) in card.lines.iter().enumerate() {
+            let y = lines_top + i as f32 * LINE_HEIGHT;
+            let line = self.fit(line, TEXT_SIZE);
+            self.draw_text(&mut img, &line, PADDING, y, TEXT_SIZE, black);
+        }
+        if !card.daily.is_empty() {
+            let y = chart_top + CHART_HEIGHT + PADDING / 2.0;
+            let size = TEXT_SIZE * 0.8;
+            let label = self.fit(&card.chart_label, size);
+            self.draw_text(&mut img, &label, PADDING, y, size, gray);
+        }
+
+        img
+    }
+
+    /// 文字的宽度，和 draw_text 的排法一样
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        let mut width = 0.0;
+        let mut last: Option<GlyphId> = None;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(prev) = last {
+                width += scaled.kern(prev, id);
+            }
+            width += scaled.h_advance(id);
+            last = Some(id);
+        }
+        width
+    }
+
+    /// 超出卡片宽度时截断，末尾加省略号，例如很长的昵称
+    fn fit(&self, text: &str, size: f32) -> String {
+        let max_width = WIDTH as f32 - PADDING * 2.0;
+        if self.text_width(text, size) <= max_width {
+            return text.to_string();
+        }
+        let mut chars: Vec<char> = text.chars().collect();
+        while !chars.is_empty() {
+            chars.pop();
+            let v = format!("{}…", chars.iter().collect::<String>().trim_end());
+            if self.text_width(&v, size) <= max_width {
+                return v;
+            }
+        }
+        "…".to_string()
+    }
+
+    /// (x, y) 为文字左上角
+    fn draw_text(
+        &self,
+        img: &mut RgbaImage,
+        text: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        color: Rgba<u8>,
+    ) {
+        let scale = PxScale::from(size);
+        let scaled = self.font.as_scaled(scale);
+        let mut caret = x;
+        let mut last: Option<GlyphId> = None;
+
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(prev) = last {
+                caret += scaled.kern(prev, id);
+            }
+            let glyph = id.with_scale_and_position(scale, point(caret, y + scaled.ascent()));
+            caret += scaled.h_advance(id);
+            last = Some(id);
+
+            let Some(outlined) = self.font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px < 0 || py < 0 || px >= img.width() as i32 || py >= img.height() as i32 {
+                    return;
+                }
+                let pixel = img.get_pixel_mut(px as u32, py as u32);
+                for (p, c) in pixel.0.iter_mut().zip(color.0).take(3) {
+                    *p = (*p as f32 * (1.0 - coverage) + c as f32 * coverage).round() as u8;
+                }
+            });
+        }
+    }
+}
@@ -1,40 +1,53 @@
-use image::codecs::gif::GifDecoder;
-use image::AnimationDecoder;
-use image::{imageops::FilterType, GenericImageView};
 use image::{DynamicImage, ImageFormat};
 use kovi::log::{error, info};
-use kovi::{chrono, tokio, AllMsgEvent, Message, RuntimeBot};
-use ndarray::{s, Array, Axis};
-use ort::GraphOptimizationLevel;
-use ort::{inputs, Session, SessionOutputs};
-use raqote::{DrawOptions, DrawTarget, LineJoin, PathBuilder, SolidSource, Source, StrokeStyle};
-use std::collections::HashMap;
-use std::io::Cursor;
-use std::ops::Deref;
+use kovi::{chrono, serde_json, tokio, AllMsgEvent, Message, RuntimeBot};
+use long_detector_core::{annotate, decode_image, Detection, Model, Thresholds};
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, RwLock};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::{Config, UserInfo};
-
-#[derive(Debug, Clone, Copy)]
-pub(crate) struct BoundingBox {
-    pub(crate) x1: f32,
-    pub(crate) y1: f32,
-    pub(crate) x2: f32,
-    pub(crate) y2: f32,
+use crate::cache::{CacheKey, DetectionCache};
+use crate::card::{Card, CardRenderer};
+use crate::i18n::i18n;
+use crate::maintenance::{mode, Mode};
+use crate::metrics::metrics;
+use crate::permission::permissions;
+use crate::report::BestImages;
+use crate::schedule::{schedules, Action};
+use crate::stats::{daily_counts, member_name, ranking, Window};
+use crate::storage::Storage;
+use crate::template::Vars;
+use crate::Config;
+
+/// 统计卡片柱状图的天数
+const CARD_DAYS: usize = 14;
+
+/// 检测结果缓存的图片数
+const CACHE_SIZE: usize = 256;
+
+/// 图片从哪里来，决定检测后怎么处理
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Trigger {
+    /// 发送“检测”，回复检测结果；message_id 为图片所在的消息，命中时撤回它，
+    /// 被回复的消息要本群开启了检测才撤回
+    Check { message_id: i32 },
+    /// 群里的普通消息，relaxed 时只回复不撤回、不禁言
+    Normal { relaxed: bool },
 }
 
 #[derive(Clone)]
 pub(crate) struct Detector {
-    pub(crate) model: Arc<Session>,
+    pub(crate) model: Arc<Model>,
+    pub(crate) model_version: String, // 模型文件的指纹，用来区分换过的模型
     pub(crate) config: Arc<Config>,
-    pub(crate) labels: Vec<&'static str>,
-    pub(crate) whitelist: Arc<RwLock<HashMap<i64, bool>>>,
-    pub(crate) user_info: Arc<Mutex<HashMap<i64, UserInfo>>>,
+    pub(crate) thresholds: Arc<Thresholds>,
+    pub(crate) storage: Arc<dyn Storage>,
     pub(crate) data_path: Arc<PathBuf>,
     pub(crate) name: String,
+    pub(crate) card: Option<Arc<CardRenderer>>,
+    pub(crate) best_images: Arc<BestImages>,
+    pub(crate) cache: Arc<DetectionCache>,
 }
 
 impl Detector {
@@ -42,34 +55,29 @@ impl Detector {
         model_bytes: &[u8],
         config: Config,
         labels: Vec<&'static str>,
-        whitelist: Arc<RwLock<HashMap<i64, bool>>>,
-        user_info: Arc<Mutex<HashMap<i64, UserInfo>>>,
+        storage: Arc<dyn Storage>,
         data_path: PathBuf,
         name: String,
+        card: Option<Arc<CardRenderer>>,
     ) -> Self {
-        let model = Arc::new(
-            Session::builder()
-                .unwrap()
-                .with_optimization_level(GraphOptimizationLevel::Level3)
-                .unwrap()
-                .with_intra_threads(4)
-                .unwrap()
-                .commit_from_memory(model_bytes)
-                .unwrap(),
-        );
+        let thresholds = config.thresholds(&labels);
+        let model = Arc::new(Model::new(model_bytes, labels).unwrap());
 
         Self {
             model,
+            model_version: format!("{:08x}", fnv1a(model_bytes)),
             config: Arc::new(config),
-            labels,
-            whitelist,
-            user_info,
+            thresholds: Arc::new(thresholds),
+            storage,
+            best_images: Arc::new(BestImages::new(data_path.join("report"), &name)),
             data_path: Arc::new(data_path),
             name,
+            card,
+            cache: Arc::new(DetectionCache::new(CACHE_SIZE)),
         }
     }
 
-    pub(crate) fn handle_admin_command(&self, e: Arc<AllMsgEvent>) {
+    pub(crate) fn handle_admin_command(&self, e: Arc<AllMsgEvent>, bot: Arc<RuntimeBot>) {
         if e.text.is_none() {
             return;
         }
@@ -83,19 +91,168 @@ impl Detector {
             return;
         }
 
-        let mut whitelist = self.whitelist.write().unwrap();
+        let command = if text == self.config.start_cmd {
+            "start"
+        } else {
+            "stop"
+        };
+        if !permissions().check(&bot, &e, command) {
+            return;
+        }
+
         let group_id = e.group_id.unwrap();
+        let (lang, vars) = self.locale(&e);
 
         if text == self.config.start_cmd {
-            whitelist.insert(group_id, true);
-            e.reply(&self.config.start_msg);
+            self.storage.set_enabled(group_id, true);
+            e.reply(i18n().configured(&lang, "start_msg", &self.config.start_msg, &vars));
         } else if text == self.config.stop_cmd {
-            whitelist.insert(group_id, false);
-            e.reply(&self.config.stop_msg);
+            self.storage.set_enabled(group_id, false);
+            e.reply(i18n().configured(&lang, "stop_msg", &self.config.stop_msg, &vars));
         }
     }
 
-    pub(crate) fn handle_my_times(&self, e: Arc<AllMsgEvent>) {
+    /// 本群的语言，以及带发送者和检测器名称的占位符
+    fn locale(&self, e: &AllMsgEvent) -> (String, Vars) {
+        let lang = i18n().lang(e.group_id);
+        let vars = Vars::for_event(e, &i18n().detector_name(&lang, &self.name));
+        (lang, vars)
+    }
+
+    pub(crate) fn stats_cmd(&self) -> String {
+        if !self.config.stats_cmd.is_empty() {
+            return self.config.stats_cmd.clone();
+        }
+        match self.config.start_cmd.strip_suffix("start") {
+            Some(v) => format!("{}stats", v),
+            None => format!("{}统计", self.name),
+        }
+    }
+
+    /// 管理本群的统计数据：
+    /// reset <QQ|@某人|group>、set <QQ|@某人> <次数>、export [csv|json]
+    pub(crate) async fn handle_stats_command(&self, e: Arc<AllMsgEvent>, bot: Arc<RuntimeBot>) {
+        if !e.is_group() {
+            return;
+        }
+
+        let text = match e.borrow_text() {
+            Some(v) => v.trim(),
+            None => return,
+        };
+        let stats_cmd = self.stats_cmd();
+        let args: Vec<&str> = match text.strip_prefix(stats_cmd.as_str()) {
+            Some(v) if v.is_empty() || v.starts_with(char::is_whitespace) => {
+                v.split_whitespace().collect()
+            }
+            _ => return,
+        };
+        if !permissions().check(&bot, &e, "stats") {
+            return;
+        }
+
+        let group_id = e.group_id.unwrap();
+        let (lang, vars) = self.locale(&e);
+        let usage = i18n().text(&lang, "stats.usage", &vars.clone().set("cmd", &stats_cmd));
+
+        match args.as_slice() {
+            ["reset", "group"] => {
+                self.storage.reset_group(group_id);
+                self.best_images.clear(group_id, None);
+                e.reply(i18n().text(&lang, "stats.reset_group", &vars));
+            }
+            ["reset", rest @ ..] => match target_user(&e, rest.first()) {
+                Some(user_id) => {
+                    self.storage.reset_user(user_id, group_id);
+                    self.best_images.clear(group_id, Some(user_id));
+                    let vars = vars.set("user", user_id);
+                    e.reply(i18n().text(&lang, "stats.reset_user", &vars));
+                }
+                None => e.reply(usage),
+            },
+            ["set", rest @ ..] => {
+                let times = rest.last().and_then(|v| v.parse::<u64>().ok());
+                let user = if rest.len() >= 2 { rest.first() } else { None };
+                match (target_user(&e, user), times) {
+                    (Some(user_id), Some(times)) => {
+                        self.storage.set_times(user_id, group_id, times);
+                        let vars = vars.set("user", user_id).set("count", times);
+                        e.reply(i18n().text(&lang, "stats.set", &vars));
+                    }
+                    _ => e.reply(usage),
+                }
+            }
+            ["export"] | ["export", "csv"] => self.export_stats(&e, &bot, &lang, false).await,
+            ["export", "json"] => self.export_stats(&e, &bot, &lang, true).await,
+            _ => e.reply(usage),
+        }
+    }
+
+    /// 把本群的统计导出为文件并上传到群文件
+    async fn export_stats(&self, e: &AllMsgEvent, bot: &RuntimeBot, lang: &str, is_json: bool) {
+        let group_id = e.group_id.unwrap();
+        let mut totals = self.storage.group_totals(group_id);
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let content = if is_json {
+            let history = self.storage.history(Some(group_id), None, 0);
+            let users: Vec<_> = totals
+                .iter()
+                .map(|(user_id, times)| serde_json::json!({"user_id": user_id, "times": times}))
+                .collect();
+            serde_json::to_string_pretty(&serde_json::json!({
+                "detector": self.name,
+                "group_id": group_id,
+                "users": users,
+                "history": history,
+            }))
+            .unwrap()
+        } else {
+            let mut content = String::from("user_id,times\n");
+            for (user_id, times) in &totals {
+                content.push_str(&format!("{},{}\n", user_id, times));
+            }
+            content
+        };
+
+        let filename = format!(
+            "{}-{}-{}.{}",
+            self.name,
+            group_id,
+            chrono::Local::now().format("%Y%m%d%H%M%S"),
+            if is_json { "json" } else { "csv" }
+        );
+        let tmp_dir = self.data_path.join("tmp");
+        let output_path = tmp_dir.join(&filename);
+        let res = match tokio::fs::create_dir_all(&tmp_dir).await {
+            Ok(_) => tokio::fs::write(&output_path, content).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            error!("保存导出文件失败: {}", err);
+            e.reply(i18n().text(lang, "stats.export_failed", &Vars::default()));
+            return;
+        }
+
+        let res = bot
+            .send_api_return(
+                "upload_group_file",
+                serde_json::json!({
+                    "group_id": group_id,
+                    "file": output_path.to_str().unwrap(),
+                    "name": filename,
+                }),
+            )
+            .await;
+        if let Err(err) = res {
+            error!("上传群文件失败: {:?}", err);
+            e.reply(i18n().text(lang, "stats.upload_failed", &Vars::default()));
+        }
+
+        delete(vec![output_path]).await;
+    }
+
+    pub(crate) async fn handle_my_times(&self, e: Arc<AllMsgEvent>, bot: Arc<RuntimeBot>) {
         if !e.is_group() {
             return;
         }
@@ -108,22 +265,243 @@ impl Detector {
         if text.trim() != self.config.my_times_cmd {
             return;
         }
+        if !permissions().check(&bot, &e, "my_times") {
+            return;
+        }
 
         let group_id = e.group_id.unwrap();
         let user_id = e.user_id;
-        let user_info_lock = self.user_info.lock().unwrap();
-
-        if let Some(user_data) = user_info_lock.get(&user_id) {
-            let group_times = user_data.group_total_times.get(&group_id).unwrap_or(&0);
-            let total_times = user_data.total_times;
-            let reply_msg = format!(
-                "你在本群发送{}的次数为: {}\n你的总发送次数为: {}",
-                self.name, group_times, total_times
+        let (lang, vars) = self.locale(&e);
+        let (group_times, total_times) = match self.storage.user_times(user_id, group_id) {
+            Some(v) => v,
+            None => {
+                e.reply(i18n().configured(
+                    &lang,
+                    "my_times_empty_msg",
+                    &self.config.my_times_empty_msg,
+                    &vars,
+                ));
+                return;
+            }
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let week_times = self
+            .storage
+            .history(Some(group_id), Some(user_id), now.saturating_sub(7 * 86400))
+            .len();
+
+        if let Some(card) = &self.card {
+            let ranking = ranking(self.storage.as_ref(), group_id, Window::All);
+            let rank = ranking.iter().position(|(id, _)| *id == user_id);
+            let line =
+                |key: &str, count: u64| i18n().text(&lang, key, &vars.clone().set("count", count));
+            let mut lines = vec![
+                line("card.group_times", group_times),
+                line("card.week_times", week_times as u64),
+                line("card.total_times", total_times),
+            ];
+            if let Some(rank) = rank {
+                let vars = vars
+                    .clone()
+                    .set("rank", rank + 1)
+                    .set("total", ranking.len());
+                lines.push(i18n().text(&lang, "card.rank", &vars));
+            }
+            let daily = daily_counts(
+                self.storage.as_ref(),
+                Some(group_id),
+                Some(user_id),
+                CARD_DAYS,
             );
-            e.reply(&reply_msg);
+            let vars = vars
+                .clone()
+                .set("nickname", member_name(&bot, group_id, user_id).await);
+            let card = card.render(&Card {
+                title: i18n().text(&lang, "card.title", &vars),
+                lines,
+                chart_label: chart_label(&lang, &daily),
+                daily,
+            });
+            self.reply_image(&e, card).await;
+            return;
+        }
+
+        let vars = vars
+            .set("count", group_times)
+            .set("week_count", week_times)
+            .set("total", total_times);
+        e.reply(i18n().configured(&lang, "my_times_msg", &self.config.my_times_msg, &vars));
+    }
+
+    /// 把图片存到 tmp 文件夹后回复，10 秒后删除
+    async fn reply_image(&self, e: &AllMsgEvent, img: image::RgbaImage) {
+        let filename = format!(
+            "{}-{}-card.png",
+            chrono::Local::now().format("%Y-%m-%d-%H-%M-%S"),
+            e.message_id
+        );
+        let output_path = self.data_path.join("tmp").join(filename);
+        if let Err(err) = tokio::fs::create_dir_all(self.data_path.join("tmp")).await {
+            error!("创建 tmp 文件夹失败: {}", err);
+            return;
+        }
+        if let Err(err) = img.save(&output_path) {
+            error!("保存统计卡片失败: {}", err);
+            return;
+        }
+
+        let mut msg = Message::new();
+        msg.push_image(output_path.to_str().unwrap());
+        e.reply(msg);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            delete(vec![output_path]).await;
+        });
+    }
+
+    pub(crate) fn rank_cmd(&self) -> String {
+        if self.config.rank_cmd.is_empty() {
+            format!("{}排行", self.name)
         } else {
-            e.reply(&format!("你还没有发送过{}哦~", self.name));
+            self.config.rank_cmd.clone()
+        }
+    }
+
+    /// 本群发送次数排行，命令后面可以跟 今日、本周 或 全部
+    pub(crate) async fn handle_rank(&self, e: Arc<AllMsgEvent>, bot: Arc<RuntimeBot>) {
+        if !e.is_group() {
+            return;
+        }
+
+        let text = match e.borrow_text() {
+            Some(v) => v.trim(),
+            None => return,
+        };
+
+        let rank_cmd = self.rank_cmd();
+        let window = match text.strip_prefix(rank_cmd.as_str()).and_then(Window::parse) {
+            Some(v) => v,
+            None => return,
+        };
+        if !permissions().check(&bot, &e, "rank") {
+            return;
         }
+
+        let group_id = e.group_id.unwrap();
+        let template = &self.config.rank_template;
+        let (lang, vars) = self.locale(&e);
+        let detector = i18n().detector_name(&lang, &self.name);
+        let vars = vars
+            .set("name", detector)
+            .set("window", i18n().text(&lang, window.key(), &Vars::default()));
+
+        let ranking = ranking(self.storage.as_ref(), group_id, window);
+        if ranking.is_empty() {
+            e.reply(i18n().configured(&lang, "rank.empty", &template.empty, &vars));
+            return;
+        }
+
+        let mut lines = Vec::new();
+        for (i, (user_id, times)) in ranking.into_iter().take(self.config.rank_size).enumerate() {
+            let nickname = member_name(&bot, group_id, user_id).await;
+            let vars = vars
+                .clone()
+                .set("rank", i + 1)
+                .set("user", user_id)
+                .set("user_id", user_id)
+                .set("count", times)
+                .set("times", times)
+                .set("nickname", nickname);
+            lines.push(i18n().configured(&lang, "rank.line", &template.line, &vars));
+        }
+
+        let header = i18n().configured(&lang, "rank.header", &template.header, &vars);
+        if let Some(card) = &self.card {
+            let daily = daily_counts(self.storage.as_ref(), Some(group_id), None, CARD_DAYS);
+            let card = card.render(&Card {
+                title: header,
+                lines,
+                chart_label: chart_label(&lang, &daily),
+                daily,
+            });
+            self.reply_image(&e, card).await;
+            return;
+        }
+
+        lines.insert(0, header);
+        e.reply(lines.join("\n"));
+    }
+
+    /// 检测一张图片，缓存里有同一张图片时不用解码
+    pub(crate) fn detect(
+        &self,
+        img_data: &[u8],
+        img_type: ImageFormat,
+    ) -> Result<Detection, Box<dyn std::error::Error>> {
+        let key = DetectionCache::key(img_data);
+        let cached = self.cache.get(key);
+        metrics().cache_hit(cached.is_some());
+        if let Some(detection) = cached {
+            return Ok(detection);
+        }
+
+        let img = decode_image(img_data, img_type)?;
+        self.infer(key, &img)
+    }
+
+    /// 解码并检测一张图片，需要画框时用，缓存里有时只省掉推理
+    pub(crate) fn detect_image(
+        &self,
+        img_data: &[u8],
+        img_type: ImageFormat,
+    ) -> Result<(DynamicImage, Detection), Box<dyn std::error::Error>> {
+        let img = decode_image(img_data, img_type)?;
+        let key = DetectionCache::key(img_data);
+        let cached = self.cache.get(key);
+        metrics().cache_hit(cached.is_some());
+        let detection = match cached {
+            Some(v) => v,
+            None => self.infer(key, &img)?,
+        };
+        Ok((img, detection))
+    }
+
+    fn infer(
+        &self,
+        key: CacheKey,
+        img: &DynamicImage,
+    ) -> Result<Detection, Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        let detection = self.model.detect(img, &self.thresholds)?;
+        metrics().image_processed(&self.name, start.elapsed());
+        self.cache.insert(key, detection.clone());
+        Ok(detection)
+    }
+
+    /// 按本群的时间表和开关决定现在是否检测，以及是否只回复不撤回、不禁言
+    pub(crate) fn scheduled(&self, group_id: i64) -> (bool, bool) {
+        match schedules().action(group_id, &self.name) {
+            Some(Action::Enable) => (true, false),
+            Some(Action::Disable) => (false, false),
+            Some(Action::Relax) => (self.storage.is_enabled(group_id), true),
+            None => (self.storage.is_enabled(group_id), false),
+        }
+    }
+
+    /// 被回复的是别人的消息，只有本群正常开启检测时才撤回，否则只回复检测结果
+    fn can_recall(&self, e: &AllMsgEvent, message_id: i32) -> bool {
+        if message_id == e.message_id {
+            return true;
+        }
+        e.group_id.is_some_and(|group_id| {
+            let (should_check, relaxed) = self.scheduled(group_id);
+            should_check && !relaxed
+        })
     }
 
     pub(crate) async fn process_images(
@@ -131,140 +509,155 @@ impl Detector {
         e: Arc<AllMsgEvent>,
         bot: Arc<RuntimeBot>,
         imgs_data: Vec<(Vec<u8>, ImageFormat)>,
-        is_check_mode: bool,
+        trigger: Trigger,
     ) {
-        if is_check_mode {
-            self.send_with_img(e, bot, imgs_data).await;
-        } else {
-            self.send_not_img(e, bot, imgs_data).await;
+        let n = imgs_data.len() as i64;
+        metrics().queue_add(n);
+        match trigger {
+            Trigger::Check { message_id } => {
+                self.send_with_img(e, bot, imgs_data, message_id).await;
+            }
+            Trigger::Normal { relaxed } => {
+                self.send_not_img(e, bot, imgs_data, relaxed).await;
+            }
         }
+        metrics().queue_add(-n);
     }
 
+    /// message_id 为图片所在的消息，回复别人的消息检测时不是本条消息
     pub(crate) async fn send_with_img(
         &self,
         e: Arc<AllMsgEvent>,
         bot: Arc<RuntimeBot>,
         imgs_data: Vec<(Vec<u8>, ImageFormat)>,
+        message_id: i32,
     ) {
-        let mut msg = Message::from(&self.config.reply_msg);
+        let (lang, vars) = self.locale(&e);
+        let title_vars = vars.clone().set("trigger", self.config.trigger_text());
+        let mut msg = Message::from(i18n().text(&lang, "check.title", &title_vars));
         let mut detected = false;
         let mut remove_img_path = Vec::new();
 
         let mut i = 0;
         for (img_data, img_type) in imgs_data {
             i += 1;
-            let (res_img, prob) = match img_type {
-                ImageFormat::Gif => {
-                    let img = extract_frame_from_gif_bytes(&img_data, 0).await.unwrap();
-                    match self.process_image_with_image(img) {
-                        Ok(v) => v,
-                        Err(err) => {
-                            error!("{}", err);
-                            return;
-                        }
-                    }
-                }
-                _ => {
-                    let original_img = image::load_from_memory(&img_data).unwrap();
-                    match self.process_image_with_image(original_img) {
-                        Ok(v) => v,
-                        Err(err) => {
-                            error!("{}", err);
-                            return;
-                        }
-                    }
+            let (img, detection) = match self.detect_image(&img_data, img_type) {
+                Ok(v) => v,
+                Err(err) => {
+                    error!("{}", err);
+                    continue;
                 }
             };
 
-            info!("{} prob: {}", self.name, prob);
+            info!("{} prob: {}", self.name, detection.max_target_score);
 
-            if prob >= self.config.trigger {
-                detected = true;
-                let filename = format!(
-                    "{}-{}-output.png",
-                    chrono::Local::now().format("%Y-%m-%d-%H-%M-%S"),
-                    i
-                );
-                let output_path = self.data_path.join("tmp").join(filename);
-
-                if let Some(parent_dir) = output_path.parent() {
-                    if !parent_dir.exists() {
-                        tokio::fs::create_dir_all(parent_dir).await.unwrap();
-                    }
+            let vars = vars.clone().set("index", i);
+            match detection.hit() {
+                Some(hit) => {
+                    let vars = vars
+                        .clone()
+                        .set("label", hit.label)
+                        .set("score", format!("{:.2}", hit.score));
+                    msg.push_text(i18n().text(&lang, "check.hit", &vars));
                 }
+                None => msg.push_text(i18n().text(&lang, "check.miss", &vars)),
+            }
+            for v in &detection.boxes {
+                let vars = vars
+                    .clone()
+                    .set("label", v.label)
+                    .set("score", format!("{:.2}", v.score))
+                    .set("trigger", format!("{:.2}", v.trigger));
+                let key = if v.is_target {
+                    "check.box"
+                } else {
+                    "check.box_ignored"
+                };
+                msg.push_text(i18n().text(&lang, key, &vars));
+            }
+
+            if detection.hit().is_none() {
+                continue;
+            }
 
-                image::save_buffer(
-                    &output_path,
-                    &res_img,
-                    res_img.width(),
-                    res_img.height(),
-                    image::ColorType::Rgba8,
-                )
-                .unwrap();
-
-                if self.config.is_reply_trigger {
-                    msg.push_text(format!("\n相似度：{:.2}", prob));
+            detected = true;
+            let res_img = annotate(&img, &detection);
+            let filename = format!(
+                "{}-{}-output.png",
+                chrono::Local::now().format("%Y-%m-%d-%H-%M-%S"),
+                i
+            );
+            let output_path = self.data_path.join("tmp").join(filename);
+
+            if let Some(parent_dir) = output_path.parent() {
+                if let Err(err) = tokio::fs::create_dir_all(parent_dir).await {
+                    error!("创建 tmp 文件夹失败: {}", err);
+                    return;
                 }
-                msg.push_image(output_path.to_str().unwrap());
+            }
 
-                remove_img_path.push(output_path);
+            if let Err(err) = res_img.save(&output_path) {
+                error!("保存检测结果图片失败: {}", err);
+                continue;
             }
+
+            msg.push_image(output_path.to_str().unwrap());
+
+            remove_img_path.push(output_path);
         }
 
+        e.reply_and_quote(msg);
         if !detected {
-            delete(remove_img_path).await;
             return;
         }
 
-        e.reply_and_quote(msg);
-        if self.config.is_delete_message {
-            bot.delete_msg(e.message_id);
+        // 全局暂停和影子模式下只回复检测结果
+        if self.config.is_delete_message
+            && mode() == Mode::Normal
+            && self.can_recall(&e, message_id)
+        {
+            bot.delete_msg(message_id);
+            metrics().deleted(&self.name);
         }
 
-        tokio::time::sleep(Duration::from_secs(10)).await;
-        delete(remove_img_path).await;
+        // 等图片发出去再删除，不占着检测队列
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            delete(remove_img_path).await;
+        });
     }
 
+    /// is_relaxed 为 true 时照常回复和计次数，但不撤回、不禁言
     pub(crate) async fn send_not_img(
         &self,
         e: Arc<AllMsgEvent>,
         bot: Arc<RuntimeBot>,
         imgs_data: Vec<(Vec<u8>, ImageFormat)>,
+        is_relaxed: bool,
     ) {
-        let mut msg = Message::from(&self.config.reply_msg);
+        let mut scores = Vec::new();
         let mut is_detected = false;
+        let mut max_score: f32 = 0.0;
+        let mut best_img = None;
 
         for (img_data, img_type) in imgs_data {
-            let prob = match img_type {
-                ImageFormat::Gif => {
-                    let img = extract_frame_from_gif_bytes(&img_data, 0).await.unwrap();
-                    match self.process_image(img) {
-                        Ok(v) => v,
-                        Err(err) => {
-                            error!("{}", err);
-                            return;
-                        }
-                    }
-                }
-                _ => {
-                    let original_img = image::load_from_memory(&img_data).unwrap();
-                    match self.process_image(original_img) {
-                        Ok(v) => v,
-                        Err(err) => {
-                            error!("{}", err);
-                            return;
-                        }
-                    }
+            let detection = match self.detect(&img_data, img_type) {
+                Ok(v) => v,
+                Err(err) => {
+                    error!("{}", err);
+                    continue;
                 }
             };
 
-            info!("{} prob: {}", self.name, prob);
+            info!("{} prob: {}", self.name, detection.max_target_score);
 
-            if prob >= self.config.trigger {
+            if let Some(hit) = detection.hit() {
                 is_detected = true;
-                if self.config.is_reply_trigger {
-                    msg.push_text(format!("\n相似度：{:.2}", prob));
+                if hit.score > max_score {
+                    max_score = hit.score;
+                    best_img = Some((img_data.clone(), img_type));
                 }
+                scores.push(hit.score);
             }
         }
 
@@ -274,233 +667,73 @@ impl Detector {
 
         let group_id = e.group_id.unwrap();
         let user_id = e.user_id;
+        if mode() == Mode::Shadow {
+            info!(
+                "影子模式，不处理群 {} 用户 {} 的{}，相似度: {}",
+                group_id, user_id, self.name, max_score
+            );
+            metrics().detected(&self.name, group_id);
+            return;
+        }
+
         let current_time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
 
-        {
-            let mut user_info_lock = self.user_info.lock().unwrap();
-            let user_data = user_info_lock.entry(user_id).or_insert_with(|| UserInfo {
-                total_times: 0,
-                group_total_times: HashMap::new(),
-                last_timestamp: HashMap::new(),
-            });
-
-            let last_timestamp = user_data.last_timestamp.get(&group_id).unwrap_or(&0);
-            let time_diff = current_time - last_timestamp;
-
-            if time_diff < self.config.ban_cooldown {
-                bot.set_group_ban(group_id, user_id, self.config.ban_duration);
-                e.reply(self.config.ban_msg.deref());
-            }
-
-            user_data.update_time(group_id, current_time);
-        }
-
-        e.reply_and_quote(msg);
-
-        if self.config.is_delete_message {
-            bot.delete_msg(e.message_id);
-        }
-    }
-
-    pub(crate) fn process_image_with_image(
-        &self,
-        original_img: DynamicImage,
-    ) -> ort::Result<(image::ImageBuffer<image::Rgba<u8>, Vec<u8>>, f32)> {
-        let (img_width, img_height) = (original_img.width(), original_img.height());
-        let img = original_img.resize_exact(640, 640, FilterType::CatmullRom);
-
-        let mut input = Array::zeros((1, 3, 640, 640));
-        for pixel in img.pixels() {
-            let x = pixel.0 as _;
-            let y = pixel.1 as _;
-            let [r, g, b, _] = pixel.2 .0;
-            input[[0, 0, y, x]] = (r as f32) / 255.;
-            input[[0, 1, y, x]] = (g as f32) / 255.;
-            input[[0, 2, y, x]] = (b as f32) / 255.;
-        }
-
-        let outputs: SessionOutputs = self.model.run(inputs!["images" => input.view()]?)?;
-        let output = outputs["output0"]
-            .try_extract_tensor::<f32>()?
-            .t()
-            .into_owned();
-
-        let mut boxes = Vec::new();
-        let output = output.slice(s![.., .., 0]);
-        for row in output.axis_iter(Axis(0)) {
-            let row: Vec<_> = row.iter().copied().collect();
-            let (class_id, prob) = row
-                .iter()
-                .skip(4)
-                .enumerate()
-                .map(|(index, value)| (index, *value))
-                .reduce(|accum, row| if row.1 > accum.1 { row } else { accum })
-                .unwrap();
-
-            if prob < 0.3 {
-                continue;
-            }
-
-            let label = self.labels[class_id];
-            let xc = row[0] / 640. * (img_width as f32);
-            let yc = row[1] / 640. * (img_height as f32);
-            let w = row[2] / 640. * (img_width as f32);
-            let h = row[3] / 640. * (img_height as f32);
-            boxes.push((
-                BoundingBox {
-                    x1: xc - w / 2.,
-                    y1: yc - h / 2.,
-                    x2: xc + w / 2.,
-                    y2: yc + h / 2.,
-                },
-                label,
-                prob,
-            ));
-        }
-
-        boxes.sort_by(|box1, box2| box2.2.total_cmp(&box1.2));
-        let mut result = Vec::new();
-
-        while !boxes.is_empty() {
-            result.push(boxes[0]);
-            boxes = boxes
-                .iter()
-                .filter(|box1| {
-                    intersection(&boxes[0].0, &box1.0) / union(&boxes[0].0, &box1.0) < 0.7
-                })
-                .copied()
-                .collect();
+        let recorded = self
+            .storage
+            .record(user_id, group_id, current_time, max_score);
+        let time_diff = current_time.saturating_sub(recorded.last_timestamp.unwrap_or(0));
+
+        if let Some((img_data, img_type)) = &best_img {
+            self.best_images.offer(
+                group_id,
+                user_id,
+                max_score,
+                current_time,
+                (img_data.as_slice(), *img_type),
+            );
         }
 
-        let mut max_prob = 0.0;
-        let mut dt = DrawTarget::new(img_width as _, img_height as _);
-
-        for (bbox, label, _confidence) in result {
-            if label == "xiong" {
-                continue;
-            }
-
-            if _confidence > max_prob {
-                max_prob = _confidence;
+        let count = self
+            .storage
+            .user_times(user_id, group_id)
+            .map_or(0, |(group_times, _)| group_times);
+        let (lang, vars) = self.locale(&e);
+        let vars = vars
+            .set("score", format!("{:.2}", max_score))
+            .set("count", count)
+            .set("ban_minutes", self.config.ban_duration.div_ceil(60));
+
+        if time_diff < self.config.ban_cooldown && !is_relaxed {
+            bot.set_group_ban(group_id, user_id, self.config.ban_duration);
+            if let Some(id) = recorded.id {
+                self.storage.mark_banned(user_id, group_id, id);
             }
-
-            let mut pb = PathBuilder::new();
-            pb.rect(bbox.x1, bbox.y1, bbox.x2 - bbox.x1, bbox.y2 - bbox.y1);
-            let path = pb.finish();
-
-            let color = SolidSource {
-                r: 255,
-                g: 0,
-                b: 0,
-                a: 255,
-            };
-
-            dt.stroke(
-                &path,
-                &Source::Solid(color),
-                &StrokeStyle {
-                    join: LineJoin::Round,
-                    width: 4.,
-                    ..StrokeStyle::default()
-                },
-                &DrawOptions::new(),
-            );
+            metrics().banned(&self.name);
+            e.reply(i18n().configured(&lang, "ban_msg", &self.config.ban_msg, &vars));
         }
-
-        let box_img: image::ImageBuffer<image::Rgba<u8>, Vec<u8>> = image::RgbaImage::from_raw(
-            img_width,
-            img_height,
-            dt.get_data()
-                .iter()
-                .flat_map(|&p| {
-                    let a = (p >> 24) & 0xff;
-                    let r = (p >> 16) & 0xff;
-                    let g = (p >> 8) & 0xff;
-                    let b = p & 0xff;
-                    vec![r as u8, g as u8, b as u8, a as u8]
-                })
-                .collect(),
-        )
-        .unwrap();
-
-        let mut res_img = image::RgbaImage::new(img_width, img_height);
-        for (x, y, pixel) in res_img.enumerate_pixels_mut() {
-            let original_pixel = original_img.get_pixel(x, y);
-            let box_pixel = box_img.get_pixel(x, y);
-
-            if box_pixel.0[3] > 0 {
-                *pixel = image::Rgba([
-                    box_pixel.0[0],
-                    box_pixel.0[1],
-                    box_pixel.0[2],
-                    box_pixel.0[3],
-                ]);
-            } else {
-                *pixel = image::Rgba([
-                    original_pixel.0[0],
-                    original_pixel.0[1],
-                    original_pixel.0[2],
-                    255,
-                ]);
+        metrics().detected(&self.name, group_id);
+
+        let mut msg =
+            Message::from(i18n().configured(&lang, "reply_msg", &self.config.reply_msg, &vars));
+        if self.config.is_reply_trigger {
+            for score in scores {
+                let vars = Vars::default().set("score", format!("{:.2}", score));
+                msg.push_text(i18n().text(&lang, "score_line", &vars));
             }
         }
 
-        Ok((res_img, max_prob))
-    }
+        e.reply_and_quote(msg);
 
-    pub(crate) fn process_image(&self, original_img: DynamicImage) -> ort::Result<f32> {
-        let img = original_img.resize_exact(640, 640, FilterType::CatmullRom);
-
-        let mut input = Array::zeros((1, 3, 640, 640));
-        for pixel in img.pixels() {
-            let x = pixel.0 as _;
-            let y = pixel.1 as _;
-            let [r, g, b, _] = pixel.2 .0;
-            input[[0, 0, y, x]] = (r as f32) / 255.;
-            input[[0, 1, y, x]] = (g as f32) / 255.;
-            input[[0, 2, y, x]] = (b as f32) / 255.;
-        }
-
-        let outputs: SessionOutputs = self.model.run(inputs!["images" => input.view()]?)?;
-        let output = outputs["output0"]
-            .try_extract_tensor::<f32>()?
-            .t()
-            .into_owned();
-
-        let mut max_prob = 0.0;
-        let output = output.slice(s![.., .., 0]);
-        for row in output.axis_iter(Axis(0)) {
-            let row: Vec<_> = row.iter().copied().collect();
-            let (class_id, prob) = row
-                .iter()
-                .skip(4)
-                .enumerate()
-                .map(|(index, value)| (index, *value))
-                .reduce(|accum, row| if row.1 > accum.1 { row } else { accum })
-                .unwrap();
-
-            if self.labels[class_id] == self.labels[0] && prob > max_prob {
-                max_prob = prob;
-            }
+        if self.config.is_delete_message && !is_relaxed {
+            bot.delete_msg(e.message_id);
+            metrics().deleted(&self.name);
         }
-
-        Ok(max_prob)
     }
 }
 
-
-pub(crate) fn intersection(box1: &BoundingBox, box2: &BoundingBox) -> f32 {
-    (box1.x2.min(box2.x2) - box1.x1.max(box2.x1)) * (box1.y2.min(box2.y2) - box1.y1.max(box2.y1))
-}
-
-pub(crate) fn union(box1: &BoundingBox, box2: &BoundingBox) -> f32 {
-    ((box1.x2 - box1.x1) * (box1.y2 - box1.y1)) + ((box2.x2 - box2.x1) * (box2.y2 - box2.y1))
-        - intersection(box1, box2)
-}
-
 pub(crate) async fn download_img(
     url: &str,
 ) -> Result<(Vec<u8>, ImageFormat), Box<dyn std::error::Error>> {
@@ -514,22 +747,19 @@ pub(crate) async fn download_img(
     }
 }
 
-pub(crate) async fn extract_frame_from_gif_bytes(
-    data: &[u8],
-    frame_index: usize,
-) -> Result<DynamicImage, Box<dyn std::error::Error>> {
-    let cursor = Cursor::new(data);
-    let decoder = GifDecoder::new(cursor)?;
-    let frames = decoder.into_frames().collect_frames()?;
-
-    if frame_index >= frames.len() {
-        return Err("Frame index out of bounds".into());
-    }
-
-    let frame = frames[frame_index].clone();
-    let dynamic_image = DynamicImage::ImageRgba8(frame.into_buffer());
+/// 统计卡片柱状图下面的说明
+fn chart_label(lang: &str, daily: &[u64]) -> String {
+    let vars = Vars::default()
+        .set("days", daily.len())
+        .set("max", daily.iter().copied().max().unwrap_or(0));
+    i18n().text(lang, "card.chart", &vars)
+}
 
-    Ok(dynamic_image)
+/// 32 位 FNV-1a，算法固定，换了 Rust 版本同一个模型的指纹也不变
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c9dc5, |hash, &b| {
+        (hash ^ b as u32).wrapping_mul(0x01000193)
+    })
 }
 
 pub(crate) async fn delete(remove_img_path: Vec<PathBuf>) {
@@ -540,3 +770,25 @@ pub(crate) async fn delete(remove_img_path: Vec<PathBuf>) {
         };
     }
 }
+
+/// 命令里 @ 的人，没有时用参数里的 QQ 号
+fn target_user(e: &AllMsgEvent, arg: Option<&&str>) -> Option<i64> {
+    let at = e
+        .message
+        .get("at")
+        .iter()
+        .find_map(|v| v.data.get("qq")?.as_str()?.parse::<i64>().ok());
+    at.or_else(|| arg?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_known_values() {
+        assert_eq!(fnv1a(b""), 0x811c9dc5);
+        assert_eq!(fnv1a(b"a"), 0xe40c292c);
+        assert_eq!(fnv1a(b"foobar"), 0xbf9cf968);
+    }
+}
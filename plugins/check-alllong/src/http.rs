@@ -0,0 +1,307 @@
+use crate::detector::Detector;
+use crate::metrics::metrics;
+use image::{DynamicImage, ImageFormat};
+use kovi::log::{error, info, warn};
+use kovi::serde_json::{self, json, Value};
+use long_detector_core::annotate;
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read};
+use std::net::SocketAddr;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// 请求体最大 20MB
+const MAX_BODY_SIZE: u64 = 20 * 1024 * 1024;
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct HttpConfig {
+    pub(crate) enabled: bool,
+    pub(crate) listen: String,
+    #[serde(default)]
+    pub(crate) metrics_enabled: bool, // 开启 /metrics，不开 enabled 也会启动服务
+    #[serde(default)]
+    pub(crate) token: String, // 不为空时所有接口都需要带 Authorization: Bearer <token>
+    #[serde(default)]
+    pub(crate) url_hosts: Vec<String>, // 允许 {"url": ...} 下载的域名，为空时不能用 url 检测
+}
+
+impl HttpConfig {
+    fn authorized(&self, request: &Request) -> bool {
+        if self.token.is_empty() {
+            return true;
+        }
+        let expected = format!("Bearer {}", self.token);
+        request
+            .headers()
+            .iter()
+            .any(|h| h.field.equiv("Authorization") && h.value.as_str() == expected)
+    }
+
+    /// 防止被当成代理访问内网
+    fn allows_url(&self, url: &str) -> Result<(), (u16, String)> {
+        if self.url_hosts.is_empty() {
+            return Err((403, "未配置 url_hosts，不能用 url 检测".to_string()));
+        }
+        let host = url_host(url).ok_or((400, "url 格式不对".to_string()))?;
+        if self.url_hosts.iter().any(|v| v.eq_ignore_ascii_case(host)) {
+            Ok(())
+        } else {
+            Err((403, format!("不允许下载 {} 的图片", host)))
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:8870".to_string(),
+            metrics_enabled: false,
+            token: String::new(),
+            url_hosts: Vec::new(),
+        }
+    }
+}
+
+/// 在单独的线程里启动 HTTP 服务，和机器人共用同一批检测器
+pub(crate) fn spawn(config: HttpConfig, detectors: Vec<Detector>) {
+    if !config.enabled && !config.metrics_enabled {
+        return;
+    }
+
+    let server = match Server::http(&config.listen) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("HTTP 服务启动失败 {}: {}", config.listen, err);
+            return;
+        }
+    };
+    info!("HTTP 检测服务已启动: http://{}", config.listen);
+    if config.token.is_empty() && !is_loopback(&config.listen) {
+        warn!("HTTP 服务监听在 {}，但没有设置 token", config.listen);
+    }
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle(request, &config, &detectors);
+        }
+    });
+}
+
+fn handle(mut request: Request, config: &HttpConfig, detectors: &[Detector]) {
+    let method = request.method().clone();
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let res = match (&method, path.as_str()) {
+        (Method::Get, "/metrics") if config.metrics_enabled && !config.authorized(&request) => {
+            Err((401, "token 不对".to_string()))
+        }
+        (Method::Get, "/metrics") if config.metrics_enabled => {
+            Ok(Response::from_data(metrics().render().into_bytes())
+                .with_header(content_type("text/plain; version=0.0.4")))
+        }
+        (Method::Post, "/detect" | "/annotate")
+            if config.enabled && !config.authorized(&request) =>
+        {
+            Err((401, "token 不对".to_string()))
+        }
+        (Method::Post, "/detect") if config.enabled => {
+            read_image(&mut request, config).map(|(data, format)| {
+                let results: Vec<Value> = detectors
+                    .iter()
+                    .map(|detector| match detector.detect(&data, format) {
+                        Ok(detection) => json!({
+                            "detector": detector.name,
+                            "hit": detection.hit().is_some(),
+                            "detection": detection,
+                        }),
+                        Err(err) => json!({
+                            "detector": detector.name,
+                            "error": err.to_string(),
+                        }),
+                    })
+                    .collect();
+                json_response(200, &json!({ "results": results }))
+            })
+        }
+        (Method::Post, "/annotate") if config.enabled => read_image(&mut request, config)
+            .and_then(|(data, format)| annotate_all(detectors, &data, format)),
+        _ => Err((404, "not found".to_string())),
+    };
+
+    let res = res.unwrap_or_else(|(status, msg)| json_response(status, &json!({ "error": msg })));
+    if let Err(err) = request.respond(res) {
+        error!("HTTP 响应失败: {}", err);
+    }
+}
+
+/// 所有检测器的目标框画在同一张图上
+fn annotate_all(
+    detectors: &[Detector],
+    data: &[u8],
+    format: ImageFormat,
+) -> Result<Response<Cursor<Vec<u8>>>, (u16, String)> {
+    let mut img: Option<DynamicImage> = None;
+    for detector in detectors {
+        let (original, detection) = detector
+            .detect_image(data, format)
+            .map_err(|err| (422, err.to_string()))?;
+        let base = img.take().unwrap_or(original);
+        img = Some(DynamicImage::ImageRgba8(annotate(&base, &detection)));
+    }
+
+    let img = img.ok_or((500, "没有可用的检测器".to_string()))?;
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|err| (500, err.to_string()))?;
+
+    Ok(Response::from_data(png).with_header(content_type("image/png")))
+}
+
+/// 请求体可以是图片本身，也可以是 {"url": "..."} 的 JSON
+fn read_image(
+    request: &mut Request,
+    config: &HttpConfig,
+) -> Result<(Vec<u8>, ImageFormat), (u16, String)> {
+    let mut body = read_limited(request.as_reader()).map_err(|err| (400, err.to_string()))?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return Err((413, "请求体超过 20MB".to_string()));
+    }
+
+    let is_json = request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv("Content-Type") && h.value.as_str().starts_with("application/json"));
+    if is_json {
+        let v: Value = serde_json::from_slice(&body).map_err(|err| (400, err.to_string()))?;
+        let url = v
+            .get("url")
+            .and_then(|v| v.as_str())
+            .ok_or((400, "缺少 url".to_string()))?;
+        config.allows_url(url)?;
+        body = download(url)?;
+    }
+
+    let format = image::guess_format(&body).map_err(|err| (415, err.to_string()))?;
+    Ok((body, format))
+}
+
+/// 不跟随重定向，免得绕过 url_hosts
+fn download(url: &str) -> Result<Vec<u8>, (u16, String)> {
+    let agent = ureq::AgentBuilder::new().redirects(0).build();
+    let response = agent
+        .get(url)
+        .call()
+        .map_err(|err| (502, err.to_string()))?;
+    if response.status() >= 300 {
+        return Err((502, format!("下载失败，状态码 {}", response.status())));
+    }
+    let data = read_limited(response.into_reader()).map_err(|err| (502, err.to_string()))?;
+    if data.len() as u64 > MAX_BODY_SIZE {
+        return Err((413, "图片超过 20MB".to_string()));
+    }
+    Ok(data)
+}
+
+/// 最多多读一个字节，用来判断是否超过 MAX_BODY_SIZE
+fn read_limited(reader: impl Read) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(MAX_BODY_SIZE + 1).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// url 里的域名，不含端口和用户名
+fn url_host(url: &str) -> Option<&str> {
+    // 反斜杠和空白在解析时会被特殊处理，可能和这里认出的域名不同，直接拒绝
+    if url
+        .chars()
+        .any(|c| c == '\\' || c.is_whitespace() || c.is_control())
+    {
+        return None;
+    }
+    let rest = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = match host.strip_prefix('[') {
+        Some(v) => v.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    (!host.is_empty()).then_some(host)
+}
+
+fn is_loopback(listen: &str) -> bool {
+    match listen.parse::<SocketAddr>() {
+        Ok(addr) => addr.ip().is_loopback(),
+        Err(_) => listen.starts_with("localhost:"),
+    }
+}
+
+fn json_response(status: u16, v: &Value) -> Response<Cursor<Vec<u8>>> {
+    Response::from_data(serde_json::to_vec(v).unwrap())
+        .with_status_code(status)
+        .with_header(content_type("application/json"))
+}
+
+fn content_type(v: &str) -> Header {
+    Header::from_bytes("Content-Type", v).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_of_url() {
+        assert_eq!(url_host("http://example.com/a.png"), Some("example.com"));
+        assert_eq!(url_host("https://example.com:8443/a"), Some("example.com"));
+        assert_eq!(url_host("http://example.com?x=1"), Some("example.com"));
+        // 用户名部分不是域名
+        assert_eq!(url_host("http://a@evil/"), Some("evil"));
+        assert_eq!(url_host("http://good.com:80@evil/"), Some("evil"));
+        assert_eq!(url_host("http://[::1]:8080/a"), Some("::1"));
+        assert_eq!(url_host("http://[::1]/"), Some("::1"));
+        assert_eq!(url_host("http://EXAMPLE.com/"), Some("EXAMPLE.com"));
+    }
+
+    #[test]
+    fn rejects_bad_urls() {
+        assert_eq!(url_host("ftp://example.com/a"), None);
+        assert_eq!(url_host("file:///etc/passwd"), None);
+        assert_eq!(url_host("example.com/a"), None);
+        assert_eq!(url_host("http:///a"), None);
+        assert_eq!(url_host("http://evil\\@good.com/"), None);
+        assert_eq!(url_host("http://good.com /"), None);
+    }
+
+    #[test]
+    fn url_hosts_allow_list() {
+        let mut config = HttpConfig::default();
+        // 没配置 url_hosts 时，监听本机也不能用 url
+        assert_eq!(config.allows_url("http://example.com/").unwrap_err().0, 403);
+
+        config.url_hosts = vec!["example.com".to_string()];
+        assert!(config.allows_url("http://example.com/a.png").is_ok());
+        assert!(config.allows_url("https://EXAMPLE.COM:443/a").is_ok());
+        assert!(config.allows_url("http://u:p@example.com/").is_ok());
+        assert_eq!(
+            config.allows_url("http://example.com@evil/").unwrap_err().0,
+            403
+        );
+        assert_eq!(
+            config.allows_url("http://sub.example.com/").unwrap_err().0,
+            403
+        );
+        assert_eq!(config.allows_url("http://[::1]/").unwrap_err().0, 403);
+        assert_eq!(config.allows_url("ftp://example.com/").unwrap_err().0, 400);
+    }
+
+    #[test]
+    fn loopback_listen() {
+        assert!(is_loopback("127.0.0.1:8870"));
+        assert!(is_loopback("[::1]:8870"));
+        assert!(is_loopback("localhost:8870"));
+        assert!(!is_loopback("0.0.0.0:8870"));
+        assert!(!is_loopback("192.168.1.2:8870"));
+        assert!(!is_loopback("example.com:8870"));
+    }
+}
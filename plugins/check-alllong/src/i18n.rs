@@ -0,0 +1,235 @@
+use crate::permission::permissions;
+use crate::store::JsonStore;
+use crate::template::{Template, Vars};
+use kovi::log::{error, info};
+use kovi::{serde_json, AllMsgEvent, RuntimeBot};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+/// 内置的语言包，data 文件夹的 locales 里同名文件会覆盖其中的条目
+const BUILTIN_LOCALES: [(&str, &str); 3] = [
+    ("zh", include_str!("../locales/zh.json")),
+    ("en", include_str!("../locales/en.json")),
+    ("ja", include_str!("../locales/ja.json")),
+];
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct I18nConfig {
+    pub(crate) default_lang: String, // 没有设置语言的群使用的语言，检测器配置里的消息属于这个语言
+    pub(crate) lang_cmd: String,     // 管理员切换本群语言的命令
+}
+
+impl Default for I18nConfig {
+    fn default() -> Self {
+        Self {
+            default_lang: "zh".to_string(),
+            lang_cmd: ".lang".to_string(),
+        }
+    }
+}
+
+type Bundle = HashMap<String, Template>;
+
+pub(crate) struct I18n {
+    config: I18nConfig,
+    bundles: HashMap<String, Bundle>,
+    groups: Option<Arc<JsonStore<HashMap<i64, String>>>>,
+}
+
+static I18N: OnceLock<I18n> = OnceLock::new();
+
+/// 未初始化时只有内置语言包，所有群都用默认语言
+pub(crate) fn i18n() -> &'static I18n {
+    I18N.get_or_init(|| I18n::new(I18nConfig::default(), None, None))
+}
+
+/// 读取语言包和每个群的语言设置，需要在使用 i18n 之前调用
+pub(crate) fn init(
+    config: I18nConfig,
+    locales_dir: &Path,
+    groups: Arc<JsonStore<HashMap<i64, String>>>,
+) {
+    if I18N
+        .set(I18n::new(config, Some(locales_dir), Some(groups)))
+        .is_err()
+    {
+        error!("语言包已经初始化过了");
+    }
+}
+
+impl I18n {
+    fn new(
+        config: I18nConfig,
+        locales_dir: Option<&Path>,
+        groups: Option<Arc<JsonStore<HashMap<i64, String>>>>,
+    ) -> Self {
+        let mut bundles: HashMap<String, Bundle> = HashMap::new();
+        for (lang, data) in BUILTIN_LOCALES {
+            bundles.insert(lang.to_string(), serde_json::from_str(data).unwrap());
+        }
+
+        if let Some(entries) = locales_dir.and_then(|v| std::fs::read_dir(v).ok()) {
+            for path in entries.flatten().map(|v| v.path()) {
+                if path.extension().and_then(|v| v.to_str()) != Some("json") {
+                    continue;
+                }
+                let Some(lang) = path.file_stem().and_then(|v| v.to_str()) else {
+                    continue;
+                };
+                let bundle: Bundle = match std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|v| serde_json::from_str(&v).map_err(|e| e.to_string()))
+                {
+                    Ok(v) => v,
+                    Err(err) => {
+                        error!("读取语言包 {:?} 失败: {}", path, err);
+                        continue;
+                    }
+                };
+                info!("已加载语言包 {}", lang);
+                bundles.entry(lang.to_string()).or_default().extend(bundle);
+            }
+        }
+
+        Self {
+            config,
+            bundles,
+            groups,
+        }
+    }
+
+    /// 本群的语言，私聊和没有设置的群用默认语言
+    pub(crate) fn lang(&self, group_id: Option<i64>) -> String {
+        group_id
+            .and_then(|id| self.groups.as_ref()?.read().get(&id).cloned())
+            .unwrap_or_else(|| self.config.default_lang.clone())
+    }
+
+    pub(crate) fn is_default(&self, lang: &str) -> bool {
+        lang == self.config.default_lang
+    }
+
+    pub(crate) fn langs(&self) -> Vec<&str> {
+        let mut langs: Vec<&str> = self.bundles.keys().map(|v| v.as_str()).collect();
+        langs.sort();
+        langs
+    }
+
+    pub(crate) fn lang_cmd(&self) -> &str {
+        &self.config.lang_cmd
+    }
+
+    /// 没有这个语言包时返回 false
+    pub(crate) fn set_lang(&self, group_id: i64, lang: &str) -> bool {
+        if !self.bundles.contains_key(lang) {
+            return false;
+        }
+        if let Some(groups) = &self.groups {
+            groups.write().insert(group_id, lang.to_string());
+        }
+        true
+    }
+
+    /// 找不到时依次用默认语言和 key 本身
+    fn get(&self, lang: &str, key: &str) -> Option<&Template> {
+        self.bundles
+            .get(lang)
+            .and_then(|v| v.get(key))
+            .or_else(|| self.bundles.get(&self.config.default_lang)?.get(key))
+    }
+
+    pub(crate) fn text(&self, lang: &str, key: &str, vars: &Vars) -> String {
+        match self.get(lang, key) {
+            Some(v) => v.render(vars),
+            None => key.to_string(),
+        }
+    }
+
+    /// 检测器配置里的消息属于默认语言，其他语言用语言包里的同名条目
+    pub(crate) fn configured(
+        &self,
+        lang: &str,
+        key: &str,
+        configured: &Template,
+        vars: &Vars,
+    ) -> String {
+        if self.is_default(lang) {
+            return configured.render(vars);
+        }
+        match self.bundles.get(lang).and_then(|v| v.get(key)) {
+            Some(v) => v.render(vars),
+            None => configured.render(vars),
+        }
+    }
+
+    /// 检测器名称的翻译，没有时用原名
+    pub(crate) fn detector_name(&self, lang: &str, name: &str) -> String {
+        self.bundles
+            .get(lang)
+            .and_then(|v| v.get(&format!("detector.{}", name)))
+            .map(|v| v.render(&Vars::default()))
+            .unwrap_or_else(|| name.to_string())
+    }
+}
+
+/// 管理员切换本群语言，例如 `.lang en`
+pub(crate) fn handle_lang_command(e: &AllMsgEvent, bot: &RuntimeBot) {
+    let Some(group_id) = e.group_id else {
+        return;
+    };
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    let lang = match text.trim().strip_prefix(i18n().lang_cmd()) {
+        Some(v) if v.starts_with(char::is_whitespace) => v.trim(),
+        _ => return,
+    };
+    if !permissions().check(bot, e, "lang") {
+        return;
+    }
+
+    if i18n().set_lang(group_id, lang) {
+        e.reply(i18n().text(lang, "lang.set", &Vars::default()));
+    } else {
+        let vars = Vars::default()
+            .set("lang", lang)
+            .set("langs", i18n().langs().join(", "));
+        let current = i18n().lang(Some(group_id));
+        e.reply(i18n().text(&current, "lang.unknown", &vars));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn builtin_locales_have_same_keys() {
+        let keys = |data: &str| -> BTreeSet<String> {
+            let bundle: Bundle = serde_json::from_str(data).unwrap();
+            bundle.into_keys().collect()
+        };
+        let (base_lang, base) = BUILTIN_LOCALES[0];
+        let base = keys(base);
+        for (lang, data) in &BUILTIN_LOCALES[1..] {
+            let other = keys(data);
+            assert_eq!(
+                base.difference(&other).collect::<Vec<_>>(),
+                Vec::<&String>::new(),
+                "{} 缺少 {} 里的条目",
+                lang,
+                base_lang
+            );
+            assert_eq!(
+                other.difference(&base).collect::<Vec<_>>(),
+                Vec::<&String>::new(),
+                "{} 缺少 {} 里的条目",
+                base_lang,
+                lang
+            );
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use crate::detector::download_img;
+use crate::metrics::metrics;
+use image::ImageFormat;
+use kovi::log::error;
+use kovi::serde_json::Value;
+use kovi::{AllMsgEvent, RuntimeBot};
+
+/// 合并转发最多展开的层数，防止套娃
+const MAX_FORWARD_DEPTH: usize = 5;
+
+/// 会被当作图片检测的消息段类型，mface 和 marketface 是表情商城的表情
+const IMAGE_SEGMENT_TYPES: [&str; 3] = ["image", "mface", "marketface"];
+
+/// 从消息段中提取图片链接
+pub(crate) fn urls_from_segments<'a>(
+    segments: impl IntoIterator<Item = (&'a str, &'a Value)>,
+) -> Vec<String> {
+    segments
+        .into_iter()
+        .filter_map(|(type_, data)| segment_url(type_, data))
+        .collect()
+}
+
+fn segment_url(type_: &str, data: &Value) -> Option<String> {
+    if !IMAGE_SEGMENT_TYPES.contains(&type_) {
+        return None;
+    }
+
+    if let Some(url) = data.get("url").and_then(|v| v.as_str()) {
+        if !url.is_empty() {
+            return Some(url.to_string());
+        }
+    }
+
+    if type_ == "image" {
+        return None;
+    }
+
+    // 部分实现的商城表情不带 url，只能用 emoji_id 拼出来
+    let emoji_id = data.get("emoji_id").and_then(|v| v.as_str())?;
+    // 不足两个字节或前两个字节不是完整的字符时没法拼
+    let dir = emoji_id.get(..2)?;
+    Some(format!(
+        "https://gxh.vip.qq.com/club/item/parcel/item/{}/{}/raw300.gif",
+        dir, emoji_id
+    ))
+}
+
+/// 本条消息里的图片链接，包括表情商城的表情
+pub(crate) fn event_image_urls(e: &AllMsgEvent) -> Vec<String> {
+    let mut urls = Vec::new();
+    for type_ in IMAGE_SEGMENT_TYPES {
+        let segments = e.message.get(type_);
+        urls.extend(urls_from_segments(
+            segments.iter().map(|x| (type_, &x.data)),
+        ));
+    }
+    urls
+}
+
+/// 被回复的消息的 id
+pub(crate) fn quoted_message_id(e: &AllMsgEvent) -> Option<i32> {
+    let reply = e.message.get("reply");
+    reply.first().and_then(|x| segment_id(&x.data))
+}
+
+/// 被回复的消息里的图片链接，通过 get_msg 拉取原消息
+pub(crate) async fn quoted_image_urls(bot: &RuntimeBot, e: &AllMsgEvent) -> Vec<String> {
+    let message_id = match quoted_message_id(e) {
+        Some(v) => v,
+        None => return Vec::new(),
+    };
+
+    let res = match bot.get_msg(message_id).await {
+        Ok(v) => v,
+        Err(err) => {
+            error!("获取被回复消息失败: {:?}", err);
+            return Vec::new();
+        }
+    };
+
+    match res.data.get("message").and_then(|v| v.as_array()) {
+        Some(segments) => urls_from_segments(segments.iter().filter_map(value_to_segment)),
+        None => Vec::new(),
+    }
+}
+
+/// 合并转发消息里的图片链接，会递归展开嵌套的合并转发
+pub(crate) async fn forward_image_urls(bot: &RuntimeBot, e: &AllMsgEvent) -> Vec<String> {
+    let mut pending: Vec<(Value, usize)> = e
+        .message
+        .get("forward")
+        .into_iter()
+        .map(|x| (x.data, 0))
+        .collect();
+
+    let mut urls = Vec::new();
+    while let Some((data, depth)) = pending.pop() {
+        if depth >= MAX_FORWARD_DEPTH {
+            continue;
+        }
+
+        // NapCat 会把内容直接放在 content 里，没有的话再走 get_forward_msg
+        let nodes = match data.get("content").and_then(|v| v.as_array()) {
+            Some(v) => v.clone(),
+            None => fetch_forward_nodes(bot, &data).await,
+        };
+
+        for node in &nodes {
+            let segments = match node_segments(node) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            urls.extend(urls_from_segments(
+                segments.iter().filter_map(value_to_segment),
+            ));
+
+            for (type_, data) in segments.iter().filter_map(value_to_segment) {
+                if type_ == "forward" {
+                    pending.push((data.clone(), depth + 1));
+                }
+            }
+        }
+    }
+
+    urls
+}
+
+async fn fetch_forward_nodes(bot: &RuntimeBot, data: &Value) -> Vec<Value> {
+    let id = match data.get("id") {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Number(n)) => n.to_string(),
+        _ => return Vec::new(),
+    };
+
+    let res = match bot.get_forward_msg(&id).await {
+        Ok(v) => v,
+        Err(err) => {
+            error!("获取合并转发消息失败: {:?}", err);
+            return Vec::new();
+        }
+    };
+
+    // OneBot 标准返回 message，NapCat 返回 messages
+    res.data
+        .get("messages")
+        .or_else(|| res.data.get("message"))
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// 转发节点里的消息段，兼容 node 消息段和 NapCat 的消息事件两种格式
+fn node_segments(node: &Value) -> Option<&Vec<Value>> {
+    let node = match node.get("type").and_then(|v| v.as_str()) {
+        Some("node") => node.get("data")?,
+        _ => node,
+    };
+
+    node.get("message")
+        .or_else(|| node.get("content"))
+        .and_then(|v| v.as_array())
+}
+
+/// 下载所有图片，失败的跳过
+pub(crate) async fn download_imgs(urls: &[String]) -> Vec<(Vec<u8>, ImageFormat)> {
+    let mut imgs_data = Vec::new();
+    for url in urls {
+        match download_img(url).await {
+            Ok((data, format)) => imgs_data.push((data, format)),
+            Err(err) => {
+                error!("下载图片失败: {}", err);
+                metrics().download_failed();
+                continue;
+            }
+        }
+    }
+    imgs_data
+}
+
+fn value_to_segment(v: &Value) -> Option<(&str, &Value)> {
+    let type_ = v.get("type")?.as_str()?;
+    let data = v.get("data")?;
+    Some((type_, data))
+}
+
+/// 消息段里的 id 可能是字符串也可能是数字
+fn segment_id(data: &Value) -> Option<i32> {
+    match data.get("id")? {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_i64().map(|v| v as i32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kovi::serde_json::json;
+
+    #[test]
+    fn url_from_segment() {
+        let data = json!({ "url": "https://example.com/a.png" });
+        for type_ in IMAGE_SEGMENT_TYPES {
+            assert_eq!(
+                segment_url(type_, &data).as_deref(),
+                Some("https://example.com/a.png")
+            );
+        }
+        assert_eq!(segment_url("text", &data), None);
+        assert_eq!(segment_url("image", &json!({ "url": "" })), None);
+    }
+
+    #[test]
+    fn market_face_fallback() {
+        let url = "https://gxh.vip.qq.com/club/item/parcel/item/ab/abcdef/raw300.gif";
+        let data = json!({ "emoji_id": "abcdef" });
+        assert_eq!(segment_url("mface", &data).as_deref(), Some(url));
+        assert_eq!(segment_url("marketface", &data).as_deref(), Some(url));
+        // 空 url 也走拼接
+        let data = json!({ "url": "", "emoji_id": "abcdef" });
+        assert_eq!(segment_url("mface", &data).as_deref(), Some(url));
+        // 普通图片不拼
+        assert_eq!(segment_url("image", &data), None);
+    }
+
+    #[test]
+    fn bad_emoji_id() {
+        assert_eq!(segment_url("mface", &json!({})), None);
+        assert_eq!(segment_url("mface", &json!({ "emoji_id": 123 })), None);
+        assert_eq!(segment_url("mface", &json!({ "emoji_id": "" })), None);
+        assert_eq!(segment_url("mface", &json!({ "emoji_id": "a" })), None);
+        // 第二个字节在汉字中间，不能 panic
+        assert_eq!(segment_url("mface", &json!({ "emoji_id": "龙图" })), None);
+        assert_eq!(segment_url("mface", &json!({ "emoji_id": "a龙" })), None);
+        assert_eq!(
+            segment_url("mface", &json!({ "emoji_id": "ab龙" })).as_deref(),
+            Some("https://gxh.vip.qq.com/club/item/parcel/item/ab/ab龙/raw300.gif")
+        );
+    }
+}
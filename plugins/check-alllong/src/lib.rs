@@ -1,26 +1,88 @@
-use detector::{download_img, Detector};
+use card::{CardConfig, CardRenderer};
+use detector::{Detector, Trigger};
+use http::HttpConfig;
+use i18n::I18nConfig;
+use images::{
+    download_imgs, event_image_urls, forward_image_urls, quoted_image_urls, quoted_message_id,
+};
 use kovi::bot::runtimebot::kovi_api::KoviApi as _;
 use kovi::log::error;
-use kovi::utils::{load_json_data, save_json_data};
+use kovi::utils::load_json_data;
 use kovi::{tokio, AllMsgEvent, PluginBuilder as p};
+use long_detector_core::builtin::{
+    long_detect_config, nailong_detect_config, DetectConfig, LONG_LABELS, LONG_MODEL,
+    NAILONG_LABELS, NAILONG_MODEL,
+};
+use long_detector_core::Thresholds;
+use maintenance::{MaintenanceConfig, Mode};
+use permission::{permissions, PermissionConfig};
+use report::ReportConfig;
+use schedule::ScheduleConfig;
 use serde::{Deserialize, Serialize};
+use stats::RankTemplate;
+use status::{Status, StatusConfig};
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex, RwLock};
-
-
+use std::path::Path;
+use std::sync::Arc;
+use storage::{spawn_pruner, JsonStorage, Storage};
+use store::{Autosaver, StoreConfig};
+use template::Template;
+
+mod cache;
+mod card;
 mod detector;
-
-pub const LONG_MODEL: &[u8] = include_bytes!("../model/long.onnx");
-pub const NAILONG_MODEL: &[u8] = include_bytes!("../model/nailong.onnx");
+mod http;
+mod i18n;
+mod images;
+mod maintenance;
+mod metrics;
+mod permission;
+mod report;
+mod schedule;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+mod stats;
+mod status;
+mod storage;
+mod store;
+mod template;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 struct UserInfo {
     total_times: u64,                     // 所有总次数
     group_total_times: HashMap<i64, u64>, // 本群总次数
     last_timestamp: HashMap<i64, u64>,
+    #[serde(default)]
+    history: Vec<Hit>, // 每次命中的记录，超过保留天数的会被清理
+    #[serde(default)]
+    last_hit_id: i64, // 最近一次命中的编号，从 1 开始递增
 }
+
+/// 一次命中
+#[derive(Clone, Serialize, Deserialize, Debug)]
+struct Hit {
+    #[serde(default)]
+    id: i64, // 旧数据没有编号，为 0
+    group_id: i64,
+    timestamp: u64,
+    score: f32,
+    #[serde(default)]
+    banned: bool,
+}
+
 impl UserInfo {
-    fn update_time(&mut self, group_id: i64, last_timestamp: u64) {
+    /// 记录一次命中，返回命中的编号
+    fn update_time(&mut self, group_id: i64, last_timestamp: u64, score: f32) -> i64 {
+        // 记录本次命中
+        self.last_hit_id += 1;
+        self.history.push(Hit {
+            id: self.last_hit_id,
+            group_id,
+            timestamp: last_timestamp,
+            score,
+            banned: false,
+        });
+
         // 更新总次数
         self.total_times += 1;
 
@@ -29,124 +91,336 @@ impl UserInfo {
 
         // 更新最后时间戳
         self.last_timestamp.insert(group_id, last_timestamp);
+
+        self.last_hit_id
+    }
+
+    fn reset_group(&mut self, group_id: i64) {
+        let times = self.group_total_times.remove(&group_id).unwrap_or(0);
+        self.total_times = self.total_times.saturating_sub(times);
+        self.last_timestamp.remove(&group_id);
+        self.history.retain(|v| v.group_id != group_id);
     }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 struct Config {
-    trigger: f32,
+    #[serde(flatten)]
+    detect: DetectConfig, // 阈值和类别
     start_cmd: String,
-    start_msg: String,
+    start_msg: Template,
     stop_cmd: String,
-    stop_msg: String,
+    stop_msg: Template,
     reply_output_img_cmd: String,
-    reply_msg: String,
+    reply_msg: Template,
     my_times_cmd: String,
+    #[serde(default = "default_my_times_msg")]
+    my_times_msg: Template,
+    #[serde(default = "default_my_times_empty_msg")]
+    my_times_empty_msg: Template,
     is_reply_trigger: bool,
     is_delete_message: bool,
     ban_cooldown: u64,
     ban_duration: usize,
-    ban_msg: String,
+    ban_msg: Template,
+    #[serde(default)]
+    rank_cmd: String, // 排行榜命令，为空时为“{名称}排行”
+    #[serde(default = "default_rank_size")]
+    rank_size: usize, // 排行榜显示的人数
+    #[serde(default)]
+    rank_template: RankTemplate,
+    #[serde(default)]
+    stats_cmd: String, // 管理统计数据的命令，为空时把 start_cmd 的 start 换成 stats
 }
 
-#[kovi::plugin]
-async fn main() {
-    let bot = p::get_runtime_bot();
-    let data_path = bot.get_data_path();
+fn default_rank_size() -> usize {
+    10
+}
 
-    // 龙图白名单
-    let long_whitelist_path = bot.get_data_path().join("long_whitelist.json");
-    let default_value = HashMap::<i64, bool>::new();
-    let long_whitelist = load_json_data(default_value, &long_whitelist_path).unwrap();
-    let long_whitelist = Arc::new(RwLock::new(long_whitelist));
-
-    // 奶龙白名单
-    let nailong_whitelist_path = bot.get_data_path().join("nailong_whitelist.json");
-    let default_value = HashMap::<i64, bool>::new();
-    let nailong_whitelist = load_json_data(default_value, &nailong_whitelist_path).unwrap();
-    let nailong_whitelist = Arc::new(RwLock::new(nailong_whitelist));
-
-    // 龙图用户信息
-    let long_user_info_path = Arc::new(data_path.join("long_user_info.json"));
-    let long_user_info: Arc<Mutex<HashMap<i64, UserInfo>>> = Arc::new(Mutex::new(
-        load_json_data(HashMap::new(), long_user_info_path.as_ref()).unwrap(),
-    ));
+fn default_my_times_msg() -> Template {
+    Template::from(
+        "你在本群发送{detector}的次数为: {count}\n最近 7 天在本群的次数为: {week_count}\n你的总发送次数为: {total}",
+    )
+}
 
-    // 奶龙用户信息
-    let nailong_user_info_path = Arc::new(data_path.join("nailong_user_info.json"));
-    let nailong_user_info: Arc<Mutex<HashMap<i64, UserInfo>>> = Arc::new(Mutex::new(
-        load_json_data(HashMap::new(), nailong_user_info_path.as_ref()).unwrap(),
-    ));
+fn default_my_times_empty_msg() -> Template {
+    Template::from("你还没有发送过{detector}哦~")
+}
+
+impl Config {
+    fn thresholds(&self, labels: &[&str]) -> Thresholds {
+        self.detect.thresholds(labels)
+    }
+
+    /// 阈值和单独设置的类别阈值，例如 "0.78, loong 0.85"
+    fn trigger_text(&self) -> String {
+        let mut classes: Vec<_> = self.detect.class_trigger.iter().collect();
+        classes.sort_by(|a, b| a.0.cmp(b.0));
+        let mut thresholds = vec![format!("{:.2}", self.detect.trigger)];
+        thresholds.extend(
+            classes
+                .into_iter()
+                .map(|(label, trigger)| format!("{} {:.2}", label, trigger)),
+        );
+        thresholds.join(", ")
+    }
+}
 
-    // 龙图检测器配置
-    let long_config = Config {
-        trigger: 0.78,
+/// 龙图检测器默认配置
+fn long_default_config() -> Config {
+    Config {
+        detect: long_detect_config(),
         start_cmd: ".lostart".to_string(),
         stop_cmd: ".lostop".to_string(),
-        start_msg: "喜欢发龙图的小朋友你们好啊，📢📢📢，本群已开启龙图戒严".to_string(),
-        stop_msg: "📢📢📢，本群已关闭龙图戒严".to_string(),
+        start_msg: Template::from("喜欢发龙图的小朋友你们好啊，📢📢📢，本群已开启龙图戒严"),
+        stop_msg: Template::from("📢📢📢，本群已关闭龙图戒严"),
         reply_output_img_cmd: "检测".to_string(),
-        reply_msg: "不准发龙图哦，再发打你👊".to_string(),
+        reply_msg: Template::from("不准发龙图哦，再发打你👊"),
         my_times_cmd: "我的龙图".to_string(),
+        my_times_msg: default_my_times_msg(),
+        my_times_empty_msg: default_my_times_empty_msg(),
         is_reply_trigger: true,
         is_delete_message: true,
         ban_cooldown: 60,
         ban_duration: 60,
-        ban_msg: "发发发发发，不准发了👊👊👊".to_string(),
-    };
+        ban_msg: Template::from("发发发发发，不准发了👊👊👊"),
+        rank_cmd: "龙图排行".to_string(),
+        rank_size: default_rank_size(),
+        rank_template: RankTemplate::default(),
+        stats_cmd: ".lostats".to_string(),
+    }
+}
 
-    // 奶龙检测器配置
-    let nailong_config = Config {
-        trigger: 0.78,
+/// 奶龙检测器默认配置
+fn nailong_default_config() -> Config {
+    Config {
+        detect: nailong_detect_config(),
         start_cmd: ".nailostart".to_string(),
         stop_cmd: ".nailostop".to_string(),
-        start_msg: "喜欢发奶龙的小朋友你们好啊，📢📢📢，本群已开启奶龙戒严".to_string(),
-        stop_msg: "📢📢📢，本群已关闭奶龙戒严".to_string(),
+        start_msg: Template::from("喜欢发奶龙的小朋友你们好啊，📢📢📢，本群已开启奶龙戒严"),
+        stop_msg: Template::from("📢📢📢，本群已关闭奶龙戒严"),
         reply_output_img_cmd: "检测".to_string(),
-        reply_msg: "不准发奶龙哦，再发打你👊".to_string(),
+        reply_msg: Template::from("不准发奶龙哦，再发打你👊"),
         my_times_cmd: "我的奶龙".to_string(),
+        my_times_msg: default_my_times_msg(),
+        my_times_empty_msg: default_my_times_empty_msg(),
         is_reply_trigger: true,
         is_delete_message: true,
         ban_cooldown: 60,
         ban_duration: 60,
-        ban_msg: "发发发发发，不准发了👊👊👊".to_string(),
-    };
+        ban_msg: Template::from("发发发发发，不准发了👊👊👊"),
+        rank_cmd: "奶龙排行".to_string(),
+        rank_size: default_rank_size(),
+        rank_template: RankTemplate::default(),
+        stats_cmd: ".nailostats".to_string(),
+    }
+}
+
+/// 白名单和用户信息存在 `{name}_whitelist.json` 和 `{name}_user_info.json`
+fn json_storage(autosaver: &Autosaver, data_path: &Path, name: &str) -> Arc<dyn Storage> {
+    let whitelist = autosaver.load(
+        HashMap::<i64, bool>::new(),
+        data_path.join(format!("{}_whitelist.json", name)),
+    );
+    let user_info = autosaver.load(
+        HashMap::<i64, UserInfo>::new(),
+        data_path.join(format!("{}_user_info.json", name)),
+    );
+    Arc::new(JsonStorage {
+        whitelist,
+        user_info,
+    })
+}
+
+/// 按配置选择存储方式，返回龙图和奶龙的存储
+fn open_storages(
+    config: &StoreConfig,
+    autosaver: &Autosaver,
+    data_path: &Path,
+) -> (Arc<dyn Storage>, Arc<dyn Storage>) {
+    if config.backend == "sqlite" {
+        #[cfg(feature = "sqlite")]
+        match sqlite::open(&data_path.join(&config.sqlite_path)) {
+            Ok(conn) => {
+                return (
+                    Arc::new(sqlite::SqliteStorage::new(conn.clone(), "long")),
+                    Arc::new(sqlite::SqliteStorage::new(conn, "nailong")),
+                );
+            }
+            Err(err) => error!("打开数据库失败: {}，改用 JSON 存储", err),
+        }
+
+        #[cfg(not(feature = "sqlite"))]
+        error!(
+            "未开启 sqlite feature，无法使用 {}，改用 JSON 存储",
+            config.sqlite_path
+        );
+    } else if config.backend != "json" {
+        error!("未知的存储方式 {}，改用 JSON 存储", config.backend);
+    }
+
+    (
+        json_storage(autosaver, data_path, "long"),
+        json_storage(autosaver, data_path, "nailong"),
+    )
+}
 
-    let nailong_config =
-        load_json_data(nailong_config, data_path.join("nailong_config.json")).unwrap();
-    let long_config = load_json_data(long_config, data_path.join("long_config.json")).unwrap();
+#[kovi::plugin]
+async fn main() {
+    let bot = p::get_runtime_bot();
+    let data_path = bot.get_data_path();
+
+    // 自动保存
+    let store_config =
+        load_json_data(StoreConfig::default(), data_path.join("store_config.json")).unwrap();
+    let autosaver = Arc::new(Autosaver::new(store_config.clone()));
+
+    // 白名单和用户信息
+    let (long_storage, nailong_storage) = open_storages(&store_config, &autosaver, &data_path);
+
+    // 语言包和每个群的语言
+    let i18n_config =
+        load_json_data(I18nConfig::default(), data_path.join("i18n_config.json")).unwrap();
+    let group_lang = autosaver.load(
+        HashMap::<i64, String>::new(),
+        data_path.join("group_lang.json"),
+    );
+    i18n::init(i18n_config, &data_path.join("locales"), group_lang);
+
+    // 命令权限
+    let permission_config = load_json_data(
+        PermissionConfig::default(),
+        data_path.join("permission_config.json"),
+    )
+    .unwrap();
+    permission::init(permission_config);
+
+    // 全局运行模式
+    let maintenance_config = load_json_data(
+        MaintenanceConfig::default(),
+        data_path.join("maintenance_config.json"),
+    )
+    .unwrap();
+    let global_mode = autosaver.load(Mode::Normal, data_path.join("global_mode.json"));
+    maintenance::init(maintenance_config, global_mode);
+
+    // 每个群的时间表
+    let schedule_config = load_json_data(
+        ScheduleConfig::default(),
+        data_path.join("schedule_config.json"),
+    )
+    .unwrap();
+    schedule::init(schedule_config);
+
+    autosaver.spawn();
+    spawn_pruner(
+        vec![long_storage.clone(), nailong_storage.clone()],
+        store_config.history_days,
+    );
+
+    let nailong_config = load_json_data(
+        nailong_default_config(),
+        data_path.join("nailong_config.json"),
+    )
+    .unwrap();
+    let long_config =
+        load_json_data(long_default_config(), data_path.join("long_config.json")).unwrap();
+
+    // 统计卡片，两个检测器共用一份字体
+    let card_config =
+        load_json_data(CardConfig::default(), data_path.join("card_config.json")).unwrap();
+    let card = CardRenderer::load(&card_config).map(Arc::new);
 
     // 创建检测器实例
     let long_detector = Detector::new(
         LONG_MODEL,
         long_config,
-        vec!["loong", "xiong"],
-        long_whitelist.clone(),
-        long_user_info.clone(),
+        LONG_LABELS.to_vec(),
+        long_storage,
         data_path.clone(),
         "龙图".to_string(),
+        card.clone(),
     );
 
     let nailong_detector = Detector::new(
         NAILONG_MODEL,
         nailong_config,
-        vec!["nailong"],
-        nailong_whitelist.clone(),
-        nailong_user_info.clone(),
+        NAILONG_LABELS.to_vec(),
+        nailong_storage,
         data_path.clone(),
         "奶龙".to_string(),
+        card,
+    );
+
+    // HTTP 检测服务
+    let http_config =
+        load_json_data(HttpConfig::default(), data_path.join("http_config.json")).unwrap();
+    http::spawn(
+        http_config,
+        vec![long_detector.clone(), nailong_detector.clone()],
+    );
+
+    // 定时战报
+    let report_config = load_json_data(
+        ReportConfig::default(),
+        data_path.join("report_config.json"),
+    )
+    .unwrap();
+    report::spawn(
+        report_config,
+        bot.clone(),
+        vec![long_detector.clone(), nailong_detector.clone()],
     );
 
+    // 帮助和状态
+    let status_config = load_json_data(
+        StatusConfig::default(),
+        data_path.join("status_config.json"),
+    )
+    .unwrap();
+    let status = Arc::new(Status::new(
+        status_config,
+        vec![long_detector.clone(), nailong_detector.clone()],
+    ));
+
+    let handle_status = {
+        let bot = bot.clone();
+        move |e: Arc<AllMsgEvent>| {
+            let status = status.clone();
+            let bot = bot.clone();
+            async move {
+                status.handle(&e, &bot);
+            }
+        }
+    };
+
+    // 私聊也可以切换全局模式
+    let handle_mode = {
+        let bot = bot.clone();
+        move |e: Arc<AllMsgEvent>| {
+            let bot = bot.clone();
+            async move {
+                maintenance::handle_mode_command(&e, &bot);
+            }
+        }
+    };
 
     let handle_admin = {
         let long_detector = long_detector.clone();
         let nailong_detector = nailong_detector.clone();
+        let bot = bot.clone();
         move |e: Arc<AllMsgEvent>| {
             let long_detector = long_detector.clone();
             let nailong_detector = nailong_detector.clone();
+            let bot = bot.clone();
             async move {
-                long_detector.handle_admin_command(e.clone());
-                nailong_detector.handle_admin_command(e);
+                i18n::handle_lang_command(&e, &bot);
+                long_detector.handle_admin_command(e.clone(), bot.clone());
+                nailong_detector.handle_admin_command(e.clone(), bot.clone());
+                long_detector
+                    .handle_stats_command(e.clone(), bot.clone())
+                    .await;
+                nailong_detector.handle_stats_command(e, bot).await;
             }
         }
     };
@@ -154,12 +428,29 @@ async fn main() {
     let handle_my_times = {
         let long_detector = long_detector.clone();
         let nailong_detector = nailong_detector.clone();
+        let bot = bot.clone();
+        move |e: Arc<AllMsgEvent>| {
+            let long_detector = long_detector.clone();
+            let nailong_detector = nailong_detector.clone();
+            let bot = bot.clone();
+            async move {
+                long_detector.handle_my_times(e.clone(), bot.clone()).await;
+                nailong_detector.handle_my_times(e, bot).await;
+            }
+        }
+    };
+
+    let handle_rank = {
+        let long_detector = long_detector.clone();
+        let nailong_detector = nailong_detector.clone();
+        let bot = bot.clone();
         move |e: Arc<AllMsgEvent>| {
             let long_detector = long_detector.clone();
             let nailong_detector = nailong_detector.clone();
+            let bot = bot.clone();
             async move {
-                long_detector.handle_my_times(e.clone());
-                nailong_detector.handle_my_times(e);
+                long_detector.handle_rank(e.clone(), bot.clone()).await;
+                nailong_detector.handle_rank(e, bot).await;
             }
         }
     };
@@ -181,36 +472,32 @@ async fn main() {
                     return;
                 }
 
-                let imgs = e.message.get("image");
-                if imgs.is_empty() {
+                // 本条消息没有图片时，检测被回复的消息，命中时撤回的也是被回复的消息
+                let mut message_id = e.message_id;
+                let mut urls = event_image_urls(&e);
+                urls.extend(forward_image_urls(&bot, &e).await);
+                if urls.is_empty() {
+                    urls = quoted_image_urls(&bot, &e).await;
+                    message_id = quoted_message_id(&e).unwrap_or(message_id);
+                }
+                if urls.is_empty() {
                     return;
                 }
-
-                let urls: Vec<_> = imgs
-                    .iter()
-                    .map(|x| x.data.get("url").unwrap().as_str().unwrap())
-                    .collect();
-
-                let mut imgs_data = Vec::new();
-                for url in &urls {
-                    match download_img(url).await {
-                        Ok((data, format)) => imgs_data.push((data, format)),
-                        Err(err) => {
-                            error!("下载图片失败: {}", err);
-                            continue;
-                        }
-                    }
+                if !permissions().check(&bot, &e, "check") {
+                    return;
                 }
 
+                let imgs_data = download_imgs(&urls).await;
                 if imgs_data.is_empty() {
                     return;
                 }
 
+                let trigger = Trigger::Check { message_id };
                 long_detector
-                    .process_images(e.clone(), bot.clone(), imgs_data.clone(), true)
+                    .process_images(e.clone(), bot.clone(), imgs_data.clone(), trigger)
                     .await;
                 nailong_detector
-                    .process_images(e, bot, imgs_data, true)
+                    .process_images(e, bot, imgs_data, trigger)
                     .await;
             }
         }
@@ -231,16 +518,14 @@ async fn main() {
                     return;
                 };
 
-                // Check whitelist
-                let long_should_check = {
-                    let whitelist = long_detector.whitelist.read().unwrap();
-                    whitelist.get(&group_id).copied().unwrap_or(false)
-                };
+                // 全局暂停时不检测，不影响各群的开关
+                if maintenance::mode() == Mode::Paused {
+                    return;
+                }
 
-                let nailong_should_check = {
-                    let whitelist = nailong_detector.whitelist.read().unwrap();
-                    whitelist.get(&group_id).copied().unwrap_or(false)
-                };
+                // Check whitelist，时间表可以临时开启、关闭或放宽检测
+                let (long_should_check, long_relaxed) = long_detector.scheduled(group_id);
+                let (nailong_should_check, nailong_relaxed) = nailong_detector.scheduled(group_id);
 
                 if !long_should_check && !nailong_should_check {
                     return;
@@ -252,39 +537,39 @@ async fn main() {
                     }
                 }
 
-                let imgs = e.message.get("image");
-                if imgs.is_empty() {
+                let mut urls = event_image_urls(&e);
+                urls.extend(forward_image_urls(&bot, &e).await);
+                if urls.is_empty() {
                     return;
                 }
 
-                let urls: Vec<_> = imgs
-                    .iter()
-                    .map(|x| x.data.get("url").unwrap().as_str().unwrap())
-                    .collect();
-
-                let mut imgs_data = Vec::new();
-                for url in &urls {
-                    match download_img(url).await {
-                        Ok((data, format)) => imgs_data.push((data, format)),
-                        Err(err) => {
-                            error!("下载图片失败: {}", err);
-                            continue;
-                        }
-                    }
-                }
-
+                let imgs_data = download_imgs(&urls).await;
                 if imgs_data.is_empty() {
                     return;
                 }
 
                 if long_should_check {
                     long_detector
-                        .process_images(e.clone(), bot.clone(), imgs_data.clone(), false)
+                        .process_images(
+                            e.clone(),
+                            bot.clone(),
+                            imgs_data.clone(),
+                            Trigger::Normal {
+                                relaxed: long_relaxed,
+                            },
+                        )
                         .await;
                 }
                 if nailong_should_check {
                     nailong_detector
-                        .process_images(e, bot, imgs_data, false)
+                        .process_images(
+                            e,
+                            bot,
+                            imgs_data,
+                            Trigger::Normal {
+                                relaxed: nailong_relaxed,
+                            },
+                        )
                         .await;
                 }
             }
@@ -292,54 +577,23 @@ async fn main() {
     };
 
     // 注册处理器
-    p::on_admin_msg(handle_admin);
+    p::on_msg(handle_mode);
+    p::on_group_msg(handle_admin);
+    p::on_group_msg(handle_status);
     p::on_group_msg(handle_my_times);
+    p::on_group_msg(handle_rank);
     p::on_group_msg(handle_check);
     p::on_group_msg(handle_normal);
 
     // 注册插件卸载处理
     p::drop({
-        let long_whitelist = long_whitelist.clone();
-        let nailong_whitelist = nailong_whitelist.clone();
-        let long_whitelist_path = Arc::new(long_whitelist_path);
-        let nailong_whitelist_path = Arc::new(nailong_whitelist_path);
+        let autosaver = autosaver.clone();
         let data_path = data_path.clone();
-        let long_user_info = long_user_info.clone();
-        let nailong_user_info = nailong_user_info.clone();
-        let long_user_info_path = long_user_info_path.clone();
-        let nailong_user_info_path = nailong_user_info_path.clone();
         move || {
-            let long_whitelist = long_whitelist.clone();
-            let nailong_whitelist = nailong_whitelist.clone();
-            let long_whitelist_path = long_whitelist_path.clone();
-            let nailong_whitelist_path = nailong_whitelist_path.clone();
+            let autosaver = autosaver.clone();
             let data_path = data_path.clone();
-            let long_user_info = long_user_info.clone();
-            let nailong_user_info = nailong_user_info.clone();
-            let long_user_info_path = long_user_info_path.clone();
-            let nailong_user_info_path = nailong_user_info_path.clone();
             async move {
-                {
-                    let long_whitelist = long_whitelist.write().unwrap();
-                    save_json_data(&*long_whitelist, long_whitelist_path.as_ref()).unwrap();
-                }
-
-                {
-                    let nailong_whitelist = nailong_whitelist.write().unwrap();
-                    save_json_data(&*nailong_whitelist, nailong_whitelist_path.as_ref()).unwrap();
-                }
-
-                {
-                    let long_user_info = long_user_info.lock().unwrap();
-                    println!("{:?}", long_user_info);
-                    save_json_data(&*long_user_info, long_user_info_path.as_ref()).unwrap();
-                }
-
-                {
-                    let nailong_user_info = nailong_user_info.lock().unwrap();
-                    println!("{:?}", nailong_user_info);
-                    save_json_data(&*nailong_user_info, nailong_user_info_path.as_ref()).unwrap();
-                }
+                autosaver.save_all();
 
                 let tmp_dir = data_path.join("tmp");
                 if let Ok(mut entries) = tokio::fs::read_dir(&tmp_dir).await {
@@ -0,0 +1,120 @@
+use crate::i18n::i18n;
+use crate::permission::permissions;
+use crate::store::JsonStore;
+use crate::template::Vars;
+use kovi::log::{error, warn};
+use kovi::{AllMsgEvent, RuntimeBot};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+
+/// 所有群共用的运行模式，不影响各群的开关
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Mode {
+    #[default]
+    Normal,
+    Paused, // 不检测
+    Shadow, // 只检测和记录日志，不回复、不撤回、不禁言，也不计次数
+}
+
+impl Mode {
+    fn parse(arg: &str) -> Option<Mode> {
+        match arg {
+            "pause" => Some(Mode::Paused),
+            "shadow" => Some(Mode::Shadow),
+            "resume" => Some(Mode::Normal),
+            _ => None,
+        }
+    }
+
+    /// 语言包里模式名称的 key
+    pub(crate) fn key(&self) -> &'static str {
+        match self {
+            Mode::Normal => "mode.normal",
+            Mode::Paused => "mode.paused",
+            Mode::Shadow => "mode.shadow",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct MaintenanceConfig {
+    pub(crate) mode_cmd: String, // 后面跟 pause、shadow 或 resume，不跟时显示当前模式
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            mode_cmd: ".lomode".to_string(),
+        }
+    }
+}
+
+struct Maintenance {
+    config: MaintenanceConfig,
+    mode: Arc<JsonStore<Mode>>,
+}
+
+static MAINTENANCE: OnceLock<Maintenance> = OnceLock::new();
+
+/// 读取保存的模式，重启后保持不变
+pub(crate) fn init(config: MaintenanceConfig, mode: Arc<JsonStore<Mode>>) {
+    let current = *mode.read();
+    if current != Mode::Normal {
+        warn!(
+            "当前为 {:?} 模式，发送 {} resume 恢复",
+            current, config.mode_cmd
+        );
+    }
+    if MAINTENANCE.set(Maintenance { config, mode }).is_err() {
+        error!("运行模式已经初始化过了");
+    }
+}
+
+/// 未初始化时为正常模式
+pub(crate) fn mode() -> Mode {
+    MAINTENANCE.get().map_or(Mode::Normal, |v| *v.mode.read())
+}
+
+pub(crate) fn mode_cmd() -> &'static str {
+    MAINTENANCE.get().map_or("", |v| v.config.mode_cmd.as_str())
+}
+
+/// 切换所有群的运行模式
+pub(crate) fn handle_mode_command(e: &AllMsgEvent, bot: &RuntimeBot) {
+    let Some(maintenance) = MAINTENANCE.get() else {
+        return;
+    };
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    let arg = match text
+        .trim()
+        .strip_prefix(maintenance.config.mode_cmd.as_str())
+    {
+        Some(v) if v.is_empty() || v.starts_with(char::is_whitespace) => v.trim(),
+        _ => return,
+    };
+    if !permissions().check(bot, e, "mode") {
+        return;
+    }
+
+    let lang = i18n().lang(e.group_id);
+    let vars = Vars::default().set("cmd", &maintenance.config.mode_cmd);
+    if arg.is_empty() {
+        let vars = vars.set("mode", i18n().text(&lang, mode().key(), &Vars::default()));
+        e.reply(i18n().text(&lang, "mode.current", &vars));
+        return;
+    }
+    let Some(new_mode) = Mode::parse(arg) else {
+        e.reply(i18n().text(&lang, "mode.usage", &vars));
+        return;
+    };
+
+    *maintenance.mode.write() = new_mode;
+    // 紧急开关不能等自动保存，马上写入文件
+    maintenance.mode.flush();
+    warn!("用户 {} 把运行模式切换为 {:?}", e.user_id, new_mode);
+    let vars = vars.set("mode", i18n().text(&lang, new_mode.key(), &Vars::default()));
+    e.reply(i18n().text(&lang, "mode.set", &vars));
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// 推理耗时直方图的桶，单位秒
+const LATENCY_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// 进程内的统计数据，按 Prometheus 文本格式导出
+#[derive(Default)]
+pub(crate) struct Metrics {
+    download_failures: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    queue_depth: AtomicI64,
+    images_processed: Mutex<HashMap<String, u64>>,
+    detections: Mutex<HashMap<(String, i64), u64>>,
+    deletions: Mutex<HashMap<String, u64>>,
+    bans: Mutex<HashMap<String, u64>>,
+    latency: Mutex<HashMap<String, Histogram>>,
+}
+
+pub(crate) fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+    pub(crate) fn download_failed(&self) {
+        self.download_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn cache_hit(&self, hit: bool) {
+        if hit {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 进入和离开检测队列的图片数
+    pub(crate) fn queue_add(&self, n: i64) {
+        self.queue_depth.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn image_processed(&self, detector: &str, latency: Duration) {
+        *self
+            .images_processed
+            .lock()
+            .unwrap()
+            .entry(detector.to_string())
+            .or_insert(0) += 1;
+
+        let secs = latency.as_secs_f64();
+        let mut latency = self.latency.lock().unwrap();
+        let histogram = latency.entry(detector.to_string()).or_default();
+        for (i, le) in LATENCY_BUCKETS.iter().enumerate() {
+            if secs <= *le {
+                histogram.buckets[i] += 1;
+            }
+        }
+        histogram.sum += secs;
+        histogram.count += 1;
+    }
+
+    pub(crate) fn detected(&self, detector: &str, group_id: i64) {
+        *self
+            .detections
+            .lock()
+            .unwrap()
+            .entry((detector.to_string(), group_id))
+            .or_insert(0) += 1;
+    }
+
+    pub(crate) fn deleted(&self, detector: &str) {
+        inc(&self.deletions, detector);
+    }
+
+    pub(crate) fn banned(&self, detector: &str) {
+        inc(&self.bans, detector);
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+
+        counter(&mut out, "long_download_failures_total", "图片下载失败次数");
+        let _ = writeln!(
+            out,
+            "long_download_failures_total {}",
+            self.download_failures.load(Ordering::Relaxed)
+        );
+
+        counter(&mut out, "long_cache_hits_total", "检测结果缓存命中次数");
+        let _ = writeln!(
+            out,
+            "long_cache_hits_total {}",
+            self.cache_hits.load(Ordering::Relaxed)
+        );
+        counter(
+            &mut out,
+            "long_cache_misses_total",
+            "检测结果缓存未命中次数",
+        );
+        let _ = writeln!(
+            out,
+            "long_cache_misses_total {}",
+            self.cache_misses.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(out, "# HELP long_queue_depth 等待或正在检测的图片数");
+        let _ = writeln!(out, "# TYPE long_queue_depth gauge");
+        let _ = writeln!(
+            out,
+            "long_queue_depth {}",
+            self.queue_depth.load(Ordering::Relaxed)
+        );
+
+        counter(&mut out, "long_images_processed_total", "检测过的图片数");
+        for (detector, v) in self.images_processed.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "long_images_processed_total{{detector=\"{}\"}} {}",
+                detector, v
+            );
+        }
+
+        counter(&mut out, "long_detections_total", "各群命中次数");
+        for ((detector, group_id), v) in self.detections.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "long_detections_total{{detector=\"{}\",group=\"{}\"}} {}",
+                detector, group_id, v
+            );
+        }
+
+        counter(&mut out, "long_messages_deleted_total", "撤回的消息数");
+        for (detector, v) in self.deletions.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "long_messages_deleted_total{{detector=\"{}\"}} {}",
+                detector, v
+            );
+        }
+
+        counter(&mut out, "long_bans_total", "禁言次数");
+        for (detector, v) in self.bans.lock().unwrap().iter() {
+            let _ = writeln!(out, "long_bans_total{{detector=\"{}\"}} {}", detector, v);
+        }
+
+        let _ = writeln!(out, "# HELP long_inference_seconds 推理耗时");
+        let _ = writeln!(out, "# TYPE long_inference_seconds histogram");
+        for (detector, histogram) in self.latency.lock().unwrap().iter() {
+            for (le, v) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(
+                    out,
+                    "long_inference_seconds_bucket{{detector=\"{}\",le=\"{}\"}} {}",
+                    detector, le, v
+                );
+            }
+            let _ = writeln!(
+                out,
+                "long_inference_seconds_bucket{{detector=\"{}\",le=\"+Inf\"}} {}",
+                detector, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "long_inference_seconds_sum{{detector=\"{}\"}} {}",
+                detector, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "long_inference_seconds_count{{detector=\"{}\"}} {}",
+                detector, histogram.count
+            );
+        }
+
+        out
+    }
+}
+
+fn inc(map: &Mutex<HashMap<String, u64>>, key: &str) {
+    *map.lock().unwrap().entry(key.to_string()).or_insert(0) += 1;
+}
+
+fn counter(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+}
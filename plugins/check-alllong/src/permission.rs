@@ -0,0 +1,222 @@
+use crate::i18n::i18n;
+use crate::template::Vars;
+use kovi::log::error;
+use kovi::{AllMsgEvent, RuntimeBot};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// 发送者的身份，后面的包含前面的权限
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Role {
+    Member,
+    Moderator,
+    GroupAdmin,
+    GroupOwner,
+    BotAdmin,
+}
+
+impl Role {
+    /// 语言包里身份名称的 key
+    fn key(&self) -> &'static str {
+        match self {
+            Role::Member => "role.member",
+            Role::Moderator => "role.moderator",
+            Role::GroupAdmin => "role.group_admin",
+            Role::GroupOwner => "role.group_owner",
+            Role::BotAdmin => "role.bot_admin",
+        }
+    }
+}
+
+/// 所有命令的名称和默认需要的身份
+const COMMANDS: [(&str, Role); 10] = [
+    ("start", Role::GroupAdmin),
+    ("stop", Role::GroupAdmin),
+    ("stats", Role::GroupAdmin),
+    ("lang", Role::GroupAdmin),
+    ("check", Role::Member),
+    ("my_times", Role::Member),
+    ("rank", Role::Member),
+    ("help", Role::Member),
+    ("status", Role::Member),
+    ("mode", Role::BotAdmin),
+];
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct PermissionConfig {
+    pub(crate) commands: HashMap<String, Role>, // 每个命令需要的身份，没写的用默认值
+    pub(crate) moderators: HashMap<i64, Vec<i64>>, // 每个群的协管 QQ 号
+}
+
+impl Default for PermissionConfig {
+    fn default() -> Self {
+        Self {
+            commands: COMMANDS
+                .iter()
+                .map(|(name, role)| (name.to_string(), *role))
+                .collect(),
+            moderators: HashMap::new(),
+        }
+    }
+}
+
+static PERMISSIONS: OnceLock<PermissionConfig> = OnceLock::new();
+
+/// 未初始化时用默认配置
+pub(crate) fn permissions() -> &'static PermissionConfig {
+    PERMISSIONS.get_or_init(PermissionConfig::default)
+}
+
+pub(crate) fn init(config: PermissionConfig) {
+    if PERMISSIONS.set(config).is_err() {
+        error!("权限配置已经初始化过了");
+    }
+}
+
+impl PermissionConfig {
+    fn required(&self, command: &str) -> Role {
+        self.commands.get(command).copied().unwrap_or_else(|| {
+            COMMANDS
+                .iter()
+                .find(|(name, _)| *name == command)
+                .map_or(Role::BotAdmin, |(_, role)| *role)
+        })
+    }
+
+    /// 发送者在本群的身份，Kovi 的机器人管理员最高
+    pub(crate) fn role(&self, bot: &RuntimeBot, e: &AllMsgEvent) -> Role {
+        let is_bot_admin = e.user_id == bot.main_admin || bot.admin.contains(&e.user_id);
+        self.role_of(
+            is_bot_admin,
+            e.sender.role.as_deref(),
+            e.group_id,
+            e.user_id,
+        )
+    }
+
+    fn role_of(
+        &self,
+        is_bot_admin: bool,
+        sender_role: Option<&str>,
+        group_id: Option<i64>,
+        user_id: i64,
+    ) -> Role {
+        if is_bot_admin {
+            return Role::BotAdmin;
+        }
+        match sender_role {
+            Some("owner") => return Role::GroupOwner,
+            Some("admin") => return Role::GroupAdmin,
+            _ => {}
+        }
+        let is_moderator = group_id
+            .and_then(|id| self.moderators.get(&id))
+            .is_some_and(|v| v.contains(&user_id));
+        if is_moderator {
+            Role::Moderator
+        } else {
+            Role::Member
+        }
+    }
+
+    /// 这个身份可以使用命令时返回 true
+    fn allows(&self, command: &str, role: Role) -> bool {
+        role >= self.required(command)
+    }
+
+    /// 发送者可以使用这个命令时返回 true，否则回复需要的身份
+    pub(crate) fn check(&self, bot: &RuntimeBot, e: &AllMsgEvent, command: &str) -> bool {
+        if self.allows(command, self.role(bot, e)) {
+            return true;
+        }
+
+        let required = self.required(command);
+        let lang = i18n().lang(e.group_id);
+        let role = i18n().text(&lang, required.key(), &Vars::default());
+        e.reply(i18n().text(
+            &lang,
+            "permission.denied",
+            &Vars::default().set("role", role),
+        ));
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn role_order() {
+        assert!(Role::Member < Role::Moderator);
+        assert!(Role::Moderator < Role::GroupAdmin);
+        assert!(Role::GroupAdmin < Role::GroupOwner);
+        assert!(Role::GroupOwner < Role::BotAdmin);
+    }
+
+    #[test]
+    fn role_of_sender() {
+        let mut config = PermissionConfig::default();
+        config.moderators.insert(1, vec![100]);
+
+        assert_eq!(
+            config.role_of(true, Some("member"), Some(1), 200),
+            Role::BotAdmin
+        );
+        assert_eq!(config.role_of(true, None, None, 200), Role::BotAdmin);
+        assert_eq!(
+            config.role_of(false, Some("owner"), Some(1), 200),
+            Role::GroupOwner
+        );
+        assert_eq!(
+            config.role_of(false, Some("admin"), Some(1), 100),
+            Role::GroupAdmin
+        );
+        assert_eq!(
+            config.role_of(false, Some("member"), Some(1), 100),
+            Role::Moderator
+        );
+        // 协管只在配置的群里有效
+        assert_eq!(
+            config.role_of(false, Some("member"), Some(2), 100),
+            Role::Member
+        );
+        assert_eq!(config.role_of(false, None, None, 100), Role::Member);
+        assert_eq!(
+            config.role_of(false, Some("member"), Some(1), 200),
+            Role::Member
+        );
+    }
+
+    #[test]
+    fn default_requirements() {
+        let config = PermissionConfig::default();
+        assert!(config.allows("check", Role::Member));
+        assert!(!config.allows("start", Role::Moderator));
+        assert!(config.allows("start", Role::GroupAdmin));
+        assert!(config.allows("start", Role::GroupOwner));
+        assert!(!config.allows("mode", Role::GroupOwner));
+        assert!(config.allows("mode", Role::BotAdmin));
+    }
+
+    #[test]
+    fn overrides_and_unknown_commands() {
+        let mut config = PermissionConfig::default();
+        config.commands.insert("start".to_string(), Role::Moderator);
+        config.commands.insert("rank".to_string(), Role::GroupOwner);
+        // 配置文件里没写的命令用默认值
+        config.commands.remove("stop");
+
+        assert!(config.allows("start", Role::Moderator));
+        assert!(!config.allows("rank", Role::GroupAdmin));
+        assert!(config.allows("rank", Role::GroupOwner));
+        assert!(!config.allows("stop", Role::Moderator));
+        assert!(config.allows("stop", Role::GroupAdmin));
+
+        assert_eq!(config.required("unknown"), Role::BotAdmin);
+        assert!(!config.allows("unknown", Role::GroupOwner));
+        assert!(config.allows("unknown", Role::BotAdmin));
+    }
+}
@@ -0,0 +1,311 @@
+use crate::detector::Detector;
+use crate::i18n::i18n;
+use crate::stats::{member_name, ranking_since};
+use crate::template::Vars;
+use image::ImageFormat;
+use kovi::chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone};
+use kovi::log::{error, info, warn};
+use kovi::{serde_json, tokio, Message, RuntimeBot};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct ReportConfig {
+    pub(crate) enabled: bool,
+    pub(crate) period: String,      // daily 或 weekly
+    pub(crate) time: String,        // 发送时间，本地时间 HH:MM
+    pub(crate) weekday: u32,        // weekly 时在周几发送，1 为周一，7 为周日
+    pub(crate) top_size: usize,     // 显示前几名
+    pub(crate) include_image: bool, // 附上本期相似度最高的图片
+    pub(crate) anonymize: bool,     // 不显示最高相似度图片是谁发的
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            period: "daily".to_string(),
+            time: "22:00".to_string(),
+            weekday: 7,
+            top_size: 3,
+            include_image: true,
+            anonymize: false,
+        }
+    }
+}
+
+impl ReportConfig {
+    fn is_weekly(&self) -> bool {
+        self.period == "weekly"
+    }
+
+    /// 下一次发送的时间，时间格式不对时返回 None
+    fn next_run(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let time = NaiveTime::parse_from_str(&self.time, "%H:%M").ok()?;
+        let today = now.date_naive();
+        let mut date = today;
+        if self.is_weekly() {
+            let weekday = self.weekday.clamp(1, 7);
+            let days = (weekday + 7 - today.weekday().number_from_monday()) % 7;
+            date = today + Duration::days(days as i64);
+        }
+
+        let step = if self.is_weekly() { 7 } else { 1 };
+        loop {
+            if let Some(next) = Local.from_local_datetime(&date.and_time(time)).earliest() {
+                if next > now {
+                    return Some(next);
+                }
+            }
+            date += Duration::days(step);
+        }
+    }
+}
+
+/// 一期里相似度最高的图片
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct BestImage {
+    pub(crate) user_id: i64,
+    pub(crate) score: f32,
+    pub(crate) timestamp: u64,
+    pub(crate) path: PathBuf,
+}
+
+/// 每个群本期相似度最高的图片，存在 data 文件夹的 report 里，发送战报后清空
+pub(crate) struct BestImages {
+    dir: PathBuf,
+    prefix: String,
+    lock: Mutex<()>,
+}
+
+impl BestImages {
+    pub(crate) fn new(dir: PathBuf, prefix: &str) -> Self {
+        Self {
+            dir,
+            prefix: prefix.to_string(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn meta_path(&self, group_id: i64) -> PathBuf {
+        self.dir.join(format!("{}_{}.json", self.prefix, group_id))
+    }
+
+    fn read_meta(&self, group_id: i64) -> Option<BestImage> {
+        let data = std::fs::read_to_string(self.meta_path(group_id)).ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    /// 比本期已有的图片相似度高时保存
+    pub(crate) fn offer(
+        &self,
+        group_id: i64,
+        user_id: i64,
+        score: f32,
+        timestamp: u64,
+        img: (&[u8], ImageFormat),
+    ) {
+        let _lock = self.lock.lock().unwrap();
+        if let Some(best) = self.read_meta(group_id) {
+            if best.score >= score {
+                return;
+            }
+            let _ = std::fs::remove_file(&best.path);
+        }
+
+        let ext = img.1.extensions_str().first().copied().unwrap_or("img");
+        let best = BestImage {
+            user_id,
+            score,
+            timestamp,
+            path: self
+                .dir
+                .join(format!("{}_{}.{}", self.prefix, group_id, ext)),
+        };
+        let res = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&best.path, img.0))
+            .and_then(|_| {
+                std::fs::write(
+                    self.meta_path(group_id),
+                    serde_json::to_string(&best).unwrap(),
+                )
+            });
+        if let Err(err) = res {
+            error!("保存本期最高相似度图片失败: {}", err);
+        }
+    }
+
+    /// 取出本期的图片，之后的命中算作下一期；图片文件由调用方发送后删除
+    pub(crate) fn take(&self, group_id: i64) -> Option<BestImage> {
+        let _lock = self.lock.lock().unwrap();
+        let best = self.read_meta(group_id);
+        let _ = std::fs::remove_file(self.meta_path(group_id));
+        best
+    }
+
+    /// 删除本期的图片，指定 user_id 时只删除这个人发的
+    pub(crate) fn clear(&self, group_id: i64, user_id: Option<i64>) {
+        let _lock = self.lock.lock().unwrap();
+        let Some(best) = self.read_meta(group_id) else {
+            return;
+        };
+        if user_id.is_some_and(|v| v != best.user_id) {
+            return;
+        }
+        let _ = std::fs::remove_file(&best.path);
+        let _ = std::fs::remove_file(self.meta_path(group_id));
+    }
+
+    /// 保存了图片的群
+    fn groups(&self) -> Vec<i64> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let prefix = format!("{}_", self.prefix);
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_prefix(&prefix)?
+                    .strip_suffix(".json")?
+                    .parse()
+                    .ok()
+            })
+            .collect()
+    }
+}
+
+/// 按配置的时间给开启检测的群发送战报
+pub(crate) fn spawn(config: ReportConfig, bot: Arc<RuntimeBot>, detectors: Vec<Detector>) {
+    if !config.enabled {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let now = Local::now();
+            let next = match config.next_run(now) {
+                Some(v) => v,
+                None => {
+                    error!("战报时间 {} 格式不对，应为 HH:MM", config.time);
+                    return;
+                }
+            };
+            info!("下一次战报时间: {}", next.format("%Y-%m-%d %H:%M"));
+            let wait = (next - now).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            send_reports(&config, &bot, &detectors).await;
+        }
+    });
+}
+
+async fn send_reports(config: &ReportConfig, bot: &RuntimeBot, detectors: &[Detector]) {
+    let days = if config.is_weekly() { 7 } else { 1 };
+    let since = (Local::now() - Duration::days(days)).timestamp().max(0) as u64;
+    // 统计的是到现在为止的 24 小时或 7 天，不是今天或本周
+    let window = if config.is_weekly() {
+        "report.last_week"
+    } else {
+        "report.last_day"
+    };
+
+    let groups: BTreeSet<i64> = detectors
+        .iter()
+        .flat_map(|v| v.storage.enabled_groups())
+        .collect();
+
+    // 不发战报的群，本期的图片也作废，不留到以后
+    for detector in detectors {
+        for group_id in detector.best_images.groups() {
+            if !groups.contains(&group_id) {
+                detector.best_images.clear(group_id, None);
+            }
+        }
+    }
+
+    for group_id in groups {
+        let lang = i18n().lang(Some(group_id));
+        let window = i18n().text(&lang, window, &Vars::default());
+        let mut lines = vec![i18n().text(
+            &lang,
+            "report.title",
+            &Vars::default().set("window", window),
+        )];
+        let mut best: Option<(String, BestImage)> = None;
+
+        for detector in detectors {
+            if !detector.storage.is_enabled(group_id) {
+                detector.best_images.clear(group_id, None);
+                continue;
+            }
+
+            let history = detector.storage.history(Some(group_id), None, since);
+            let bans = history.iter().filter(|v| v.banned).count();
+            let name = i18n().detector_name(&lang, &detector.name);
+            let vars = Vars::default()
+                .set("detector", &name)
+                .set("count", history.len())
+                .set("bans", bans);
+            lines.push(i18n().text(&lang, "report.summary", &vars));
+
+            for (i, (user_id, times)) in ranking_since(&history)
+                .into_iter()
+                .take(config.top_size)
+                .enumerate()
+            {
+                let vars = Vars::default()
+                    .set("rank", i + 1)
+                    .set("user", user_id)
+                    .set("nickname", member_name(bot, group_id, user_id).await)
+                    .set("count", times);
+                lines.push(i18n().text(&lang, "report.line", &vars));
+            }
+
+            // 两个检测器只保留相似度更高的一张
+            if let Some(v) = detector.best_images.take(group_id) {
+                let is_better = best.as_ref().is_none_or(|(_, b)| v.score > b.score);
+                let dropped = if is_better {
+                    best.replace((name, v)).map(|(_, b)| b)
+                } else {
+                    Some(v)
+                };
+                if let Some(dropped) = dropped {
+                    let _ = tokio::fs::remove_file(&dropped.path).await;
+                }
+            }
+        }
+
+        let mut msg = Message::from(lines.join("\n"));
+        if let Some((name, v)) = &best {
+            if config.include_image {
+                let sender = if config.anonymize {
+                    i18n().text(&lang, "report.anonymous", &Vars::default())
+                } else {
+                    member_name(bot, group_id, v.user_id).await
+                };
+                let vars = Vars::default()
+                    .set("detector", name)
+                    .set("score", format!("{:.2}", v.score))
+                    .set("nickname", sender);
+                msg.push_text(i18n().text(&lang, "report.best", &vars));
+                msg.push_image(v.path.to_str().unwrap());
+            }
+        }
+
+        info!("发送战报到群 {}", group_id);
+        bot.send_group_msg(group_id, msg);
+
+        if let Some((_, v)) = best {
+            // 等图片发出去再删除
+            tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+                if let Err(err) = tokio::fs::remove_file(&v.path).await {
+                    warn!("删除 {:?} 失败: {}", v.path, err);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use kovi::chrono::{Datelike, FixedOffset, Local, NaiveDateTime, NaiveTime, Utc};
+use kovi::log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// 时间段内对检测的处理
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Action {
+    Enable,  // 本群没开启也检测
+    Disable, // 本群开启了也不检测
+    Relax,   // 照常检测、回复和计次数，但不撤回、不禁言
+}
+
+impl Action {
+    /// 语言包里处理方式名称的 key
+    pub(crate) fn key(&self) -> &'static str {
+        match self {
+            Action::Enable => "schedule.enable",
+            Action::Disable => "schedule.disable",
+            Action::Relax => "schedule.relax",
+        }
+    }
+}
+
+/// 一个时间段，结束时间早于开始时间时跨过零点，相同时为开始后的 24 小时
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct Rule {
+    #[serde(default)]
+    pub(crate) weekdays: Vec<u32>, // 开始时间在周几，1 为周一，7 为周日，为空时每天
+    pub(crate) start: String, // HH:MM
+    pub(crate) end: String,   // HH:MM
+    pub(crate) action: Action,
+    #[serde(default)]
+    pub(crate) detectors: Vec<String>, // 对哪些检测器生效，例如 "龙图"，为空时全部
+}
+
+impl Rule {
+    fn times(&self) -> Option<(NaiveTime, NaiveTime)> {
+        let start = NaiveTime::parse_from_str(&self.start, "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(&self.end, "%H:%M").ok()?;
+        Some((start, end))
+    }
+
+    fn on(&self, weekday: u32) -> bool {
+        self.weekdays.is_empty() || self.weekdays.contains(&weekday)
+    }
+
+    fn matches(&self, now: NaiveDateTime, detector: &str) -> bool {
+        if !self.detectors.is_empty() && !self.detectors.iter().any(|v| v == detector) {
+            return false;
+        }
+        let Some((start, end)) = self.times() else {
+            return false;
+        };
+
+        let time = now.time();
+        let today = now.weekday().number_from_monday();
+        let yesterday = now.weekday().pred().number_from_monday();
+        if start < end {
+            self.on(today) && start <= time && time < end
+        } else {
+            (self.on(today) && start <= time) || (self.on(yesterday) && time < end)
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub(crate) struct GroupSchedule {
+    #[serde(default)]
+    pub(crate) timezone: String, // 固定的 UTC 偏移，例如 "+08:00"，不会随夏令时变化；为空时用本机时区
+    pub(crate) rules: Vec<Rule>, // 按顺序使用第一个符合的时间段
+}
+
+impl GroupSchedule {
+    fn now(&self) -> NaiveDateTime {
+        match parse_offset(&self.timezone) {
+            Some(offset) => Utc::now().with_timezone(&offset).naive_local(),
+            None => Local::now().naive_local(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub(crate) struct ScheduleConfig {
+    pub(crate) groups: HashMap<i64, GroupSchedule>,
+}
+
+static SCHEDULES: OnceLock<ScheduleConfig> = OnceLock::new();
+
+/// 未初始化时没有时间表
+pub(crate) fn schedules() -> &'static ScheduleConfig {
+    SCHEDULES.get_or_init(ScheduleConfig::default)
+}
+
+/// 检查时间格式，写错的时间段不会生效
+pub(crate) fn init(config: ScheduleConfig) {
+    for (group_id, schedule) in &config.groups {
+        if !schedule.timezone.is_empty() && parse_offset(&schedule.timezone).is_none() {
+            error!(
+                "群 {} 的时区 {} 格式不对，应为 +08:00，改用本机时区",
+                group_id, schedule.timezone
+            );
+        }
+        for rule in &schedule.rules {
+            if rule.times().is_none() {
+                error!(
+                    "群 {} 的时间段 {}-{} 格式不对，应为 HH:MM",
+                    group_id, rule.start, rule.end
+                );
+            }
+        }
+    }
+    if SCHEDULES.set(config).is_err() {
+        error!("时间表已经初始化过了");
+    }
+}
+
+impl ScheduleConfig {
+    /// 本群现在对这个检测器的处理，没有符合的时间段时为 None
+    pub(crate) fn action(&self, group_id: i64, detector: &str) -> Option<Action> {
+        let schedule = self.groups.get(&group_id)?;
+        let now = schedule.now();
+        schedule
+            .rules
+            .iter()
+            .find(|v| v.matches(now, detector))
+            .map(|v| v.action)
+    }
+}
+
+/// 解析 "+08:00"、"-5"、"UTC+8" 这样的 UTC 偏移
+fn parse_offset(v: &str) -> Option<FixedOffset> {
+    let v = v.trim();
+    let v = v.strip_prefix("UTC").unwrap_or(v);
+    let (sign, rest) = match v.chars().next()? {
+        '+' => (1, &v[1..]),
+        '-' => (-1, &v[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h.parse::<i32>().ok()?, m.parse::<i32>().ok()?),
+        None => (rest.parse::<i32>().ok()?, 0),
+    };
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kovi::chrono::NaiveDate;
+
+    /// 2024-01-01 是周一
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn rule(weekdays: Vec<u32>, start: &str, end: &str) -> Rule {
+        Rule {
+            weekdays,
+            start: start.to_string(),
+            end: end.to_string(),
+            action: Action::Disable,
+            detectors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn same_day_range() {
+        let rule = rule(vec![], "09:00", "18:00");
+        assert!(rule.matches(at(1, 9, 0), "龙图"));
+        assert!(rule.matches(at(1, 17, 59), "龙图"));
+        assert!(!rule.matches(at(1, 18, 0), "龙图"));
+        assert!(!rule.matches(at(1, 8, 59), "龙图"));
+    }
+
+    #[test]
+    fn cross_midnight() {
+        let rule = rule(vec![], "22:00", "06:00");
+        assert!(rule.matches(at(1, 23, 0), "龙图"));
+        assert!(rule.matches(at(2, 5, 59), "龙图"));
+        assert!(!rule.matches(at(2, 6, 0), "龙图"));
+        assert!(!rule.matches(at(1, 21, 59), "龙图"));
+    }
+
+    #[test]
+    fn cross_midnight_uses_start_weekday() {
+        // 周五晚上开始，周六早上结束
+        let rule = rule(vec![5], "22:00", "06:00");
+        assert!(rule.matches(at(5, 23, 0), "龙图"));
+        assert!(rule.matches(at(6, 5, 0), "龙图"));
+        assert!(!rule.matches(at(5, 5, 0), "龙图"));
+        assert!(!rule.matches(at(6, 23, 0), "龙图"));
+    }
+
+    #[test]
+    fn same_start_and_end_is_24_hours() {
+        let rule = rule(vec![1], "08:00", "08:00");
+        assert!(rule.matches(at(1, 8, 0), "龙图"));
+        assert!(rule.matches(at(2, 7, 59), "龙图"));
+        assert!(!rule.matches(at(2, 8, 0), "龙图"));
+        assert!(!rule.matches(at(1, 7, 59), "龙图"));
+    }
+
+    #[test]
+    fn sunday_to_monday() {
+        // 周日的前一天是周六，周一的前一天是周日
+        let rule = rule(vec![7], "23:00", "01:00");
+        assert!(rule.matches(at(7, 23, 30), "龙图"));
+        assert!(rule.matches(at(8, 0, 30), "龙图"));
+        assert!(!rule.matches(at(7, 0, 30), "龙图"));
+    }
+
+    #[test]
+    fn detectors_and_bad_times() {
+        let mut only_long = rule(vec![], "00:00", "00:00");
+        only_long.detectors = vec!["龙图".to_string()];
+        assert!(only_long.matches(at(1, 12, 0), "龙图"));
+        assert!(!only_long.matches(at(1, 12, 0), "奶龙"));
+
+        assert!(!rule(vec![], "25:00", "06:00").matches(at(1, 12, 0), "龙图"));
+        assert!(!rule(vec![], "9", "18:00").matches(at(1, 12, 0), "龙图"));
+    }
+
+    #[test]
+    fn offsets() {
+        let secs = |v: &str| parse_offset(v).map(|v| v.local_minus_utc());
+        assert_eq!(secs("+08:00"), Some(8 * 3600));
+        assert_eq!(secs("UTC+8"), Some(8 * 3600));
+        assert_eq!(secs(" -5 "), Some(-5 * 3600));
+        assert_eq!(secs("+05:30"), Some(5 * 3600 + 30 * 60));
+        assert_eq!(secs("UTC-09:30"), Some(-(9 * 3600 + 30 * 60)));
+        assert_eq!(secs(""), None);
+        assert_eq!(secs("8"), None);
+        assert_eq!(secs("Asia/Shanghai"), None);
+        assert_eq!(secs("+ab"), None);
+        assert_eq!(secs("+24"), None);
+    }
+}
@@ -0,0 +1,424 @@
+//! SQLite 存储，需要开启 `sqlite` feature
+
+use crate::storage::{HitRecord, Recorded, Storage};
+use crate::UserInfo;
+use kovi::log::error;
+use kovi::serde_json;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS whitelist (
+    detector TEXT NOT NULL,
+    group_id INTEGER NOT NULL,
+    enabled INTEGER NOT NULL,
+    PRIMARY KEY (detector, group_id)
+);
+CREATE TABLE IF NOT EXISTS user_counts (
+    detector TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    group_id INTEGER NOT NULL,
+    times INTEGER NOT NULL,
+    last_timestamp INTEGER NOT NULL,
+    PRIMARY KEY (detector, user_id, group_id)
+);
+CREATE TABLE IF NOT EXISTS detections (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    detector TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    group_id INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    score REAL NOT NULL,
+    banned INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS detections_group ON detections (detector, group_id, timestamp);
+CREATE INDEX IF NOT EXISTS detections_user ON detections (detector, user_id, timestamp);
+";
+
+/// 打开数据库并建表，多个检测器共用一个连接
+pub(crate) fn open(path: &Path) -> rusqlite::Result<Arc<Mutex<Connection>>> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+    conn.execute_batch(SCHEMA)?;
+
+    // 旧版本的 detections 表没有 banned 列
+    let has_banned: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('detections') WHERE name = 'banned'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_banned {
+        conn.execute_batch("ALTER TABLE detections ADD COLUMN banned INTEGER NOT NULL DEFAULT 0;")?;
+    }
+
+    Ok(Arc::new(Mutex::new(conn)))
+}
+
+pub(crate) struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+    detector: String,
+}
+
+impl SqliteStorage {
+    /// detector 为 long 或 nailong，和 JSON 文件名的前缀一致
+    pub(crate) fn new(conn: Arc<Mutex<Connection>>, detector: &str) -> Self {
+        Self {
+            conn,
+            detector: detector.to_string(),
+        }
+    }
+}
+
+impl SqliteStorage {
+    fn record_tx(
+        &self,
+        conn: &mut Connection,
+        user_id: i64,
+        group_id: i64,
+        timestamp: u64,
+        score: f32,
+    ) -> rusqlite::Result<Recorded> {
+        let tx = conn.transaction()?;
+        let last_timestamp = tx
+            .query_row(
+                "SELECT last_timestamp FROM user_counts
+                 WHERE detector = ?1 AND user_id = ?2 AND group_id = ?3",
+                params![self.detector, user_id, group_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+
+        tx.execute(
+            "INSERT INTO detections (detector, user_id, group_id, timestamp, score)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                self.detector,
+                user_id,
+                group_id,
+                timestamp as i64,
+                score as f64
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        tx.execute(
+            "INSERT INTO user_counts (detector, user_id, group_id, times, last_timestamp)
+             VALUES (?1, ?2, ?3, 1, ?4)
+             ON CONFLICT (detector, user_id, group_id)
+             DO UPDATE SET times = times + 1, last_timestamp = excluded.last_timestamp",
+            params![self.detector, user_id, group_id, timestamp as i64],
+        )?;
+        tx.commit()?;
+
+        Ok(Recorded {
+            last_timestamp: last_timestamp.map(|v| v as u64),
+            id: Some(id),
+        })
+    }
+
+    /// user_id 为 None 时删除整个群
+    fn delete_tx(
+        &self,
+        conn: &mut Connection,
+        user_id: Option<i64>,
+        group_id: i64,
+    ) -> rusqlite::Result<()> {
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM user_counts
+             WHERE detector = ?1 AND group_id = ?2 AND (?3 IS NULL OR user_id = ?3)",
+            params![self.detector, group_id, user_id],
+        )?;
+        tx.execute(
+            "DELETE FROM detections
+             WHERE detector = ?1 AND group_id = ?2 AND (?3 IS NULL OR user_id = ?3)",
+            params![self.detector, group_id, user_id],
+        )?;
+        tx.commit()
+    }
+
+    fn enabled_groups_query(&self, conn: &Connection) -> rusqlite::Result<Vec<i64>> {
+        let mut stmt = conn
+            .prepare_cached("SELECT group_id FROM whitelist WHERE detector = ?1 AND enabled = 1")?;
+        let rows = stmt.query_map(params![self.detector], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    fn group_totals_query(
+        &self,
+        conn: &Connection,
+        group_id: i64,
+    ) -> rusqlite::Result<Vec<(i64, u64)>> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT user_id, times FROM user_counts WHERE detector = ?1 AND group_id = ?2",
+        )?;
+        let rows = stmt.query_map(params![self.detector, group_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        rows.collect()
+    }
+
+    fn history_query(
+        &self,
+        conn: &Connection,
+        group_id: Option<i64>,
+        user_id: Option<i64>,
+        since: u64,
+    ) -> rusqlite::Result<Vec<HitRecord>> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT user_id, group_id, timestamp, score, banned FROM detections
+             WHERE detector = ?1 AND timestamp >= ?2
+               AND (?3 IS NULL OR group_id = ?3)
+               AND (?4 IS NULL OR user_id = ?4)
+             ORDER BY timestamp",
+        )?;
+        let rows = stmt.query_map(
+            params![self.detector, since as i64, group_id, user_id],
+            |row| {
+                Ok(HitRecord {
+                    user_id: row.get(0)?,
+                    group_id: row.get(1)?,
+                    timestamp: row.get::<_, i64>(2)? as u64,
+                    score: row.get::<_, f64>(3)? as f32,
+                    banned: row.get(4)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn is_enabled(&self, group_id: i64) -> bool {
+        let conn = self.conn.lock().unwrap();
+        let res = conn
+            .query_row(
+                "SELECT enabled FROM whitelist WHERE detector = ?1 AND group_id = ?2",
+                params![self.detector, group_id],
+                |row| row.get::<_, bool>(0),
+            )
+            .optional();
+
+        match res {
+            Ok(v) => v.unwrap_or(false),
+            Err(err) => {
+                error!("读取白名单失败: {}", err);
+                false
+            }
+        }
+    }
+
+    fn set_enabled(&self, group_id: i64, enabled: bool) {
+        let conn = self.conn.lock().unwrap();
+        if let Err(err) = conn.execute(
+            "INSERT INTO whitelist (detector, group_id, enabled) VALUES (?1, ?2, ?3)
+             ON CONFLICT (detector, group_id) DO UPDATE SET enabled = excluded.enabled",
+            params![self.detector, group_id, enabled],
+        ) {
+            error!("写入白名单失败: {}", err);
+        }
+    }
+
+    fn enabled_groups(&self) -> Vec<i64> {
+        let conn = self.conn.lock().unwrap();
+        match self.enabled_groups_query(&conn) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("读取白名单失败: {}", err);
+                Vec::new()
+            }
+        }
+    }
+
+    fn mark_banned(&self, user_id: i64, group_id: i64, id: i64) {
+        let conn = self.conn.lock().unwrap();
+        if let Err(err) = conn.execute(
+            "UPDATE detections SET banned = 1
+             WHERE id = ?1 AND detector = ?2 AND user_id = ?3 AND group_id = ?4",
+            params![id, self.detector, user_id, group_id],
+        ) {
+            error!("写入禁言记录失败: {}", err);
+        }
+    }
+
+    fn record(&self, user_id: i64, group_id: i64, timestamp: u64, score: f32) -> Recorded {
+        let mut conn = self.conn.lock().unwrap();
+        match self.record_tx(&mut conn, user_id, group_id, timestamp, score) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("写入命中记录失败: {}", err);
+                Recorded {
+                    last_timestamp: None,
+                    id: None,
+                }
+            }
+        }
+    }
+
+    fn user_times(&self, user_id: i64, group_id: i64) -> Option<(u64, u64)> {
+        let conn = self.conn.lock().unwrap();
+        let res = conn.query_row(
+            "SELECT COALESCE(SUM(CASE WHEN group_id = ?3 THEN times ELSE 0 END), 0),
+                    COALESCE(SUM(times), 0),
+                    COUNT(*)
+             FROM user_counts WHERE detector = ?1 AND user_id = ?2",
+            params![self.detector, user_id, group_id],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            },
+        );
+
+        match res {
+            Ok((_, _, 0)) => None,
+            Ok((group_times, total_times, _)) => Some((group_times as u64, total_times as u64)),
+            Err(err) => {
+                error!("读取发送次数失败: {}", err);
+                None
+            }
+        }
+    }
+
+    fn group_totals(&self, group_id: i64) -> Vec<(i64, u64)> {
+        let conn = self.conn.lock().unwrap();
+        match self.group_totals_query(&conn, group_id) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("读取本群次数失败: {}", err);
+                Vec::new()
+            }
+        }
+    }
+
+    fn history(&self, group_id: Option<i64>, user_id: Option<i64>, since: u64) -> Vec<HitRecord> {
+        let conn = self.conn.lock().unwrap();
+        match self.history_query(&conn, group_id, user_id, since) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("读取命中记录失败: {}", err);
+                Vec::new()
+            }
+        }
+    }
+
+    fn reset_user(&self, user_id: i64, group_id: i64) {
+        let mut conn = self.conn.lock().unwrap();
+        if let Err(err) = self.delete_tx(&mut conn, Some(user_id), group_id) {
+            error!("清空用户统计失败: {}", err);
+        }
+    }
+
+    fn reset_group(&self, group_id: i64) {
+        let mut conn = self.conn.lock().unwrap();
+        if let Err(err) = self.delete_tx(&mut conn, None, group_id) {
+            error!("清空本群统计失败: {}", err);
+        }
+    }
+
+    fn set_times(&self, user_id: i64, group_id: i64, times: u64) {
+        let conn = self.conn.lock().unwrap();
+        if let Err(err) = conn.execute(
+            "INSERT INTO user_counts (detector, user_id, group_id, times, last_timestamp)
+             VALUES (?1, ?2, ?3, ?4, 0)
+             ON CONFLICT (detector, user_id, group_id) DO UPDATE SET times = excluded.times",
+            params![self.detector, user_id, group_id, times as i64],
+        ) {
+            error!("修改次数失败: {}", err);
+        }
+    }
+
+    fn prune(&self, before: u64) -> usize {
+        let conn = self.conn.lock().unwrap();
+        match conn.execute(
+            "DELETE FROM detections WHERE detector = ?1 AND timestamp < ?2",
+            params![self.detector, before as i64],
+        ) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("清理命中记录失败: {}", err);
+                0
+            }
+        }
+    }
+}
+
+/// 导入的条数
+#[derive(Debug, Default)]
+pub struct MigrateReport {
+    pub whitelist: usize,
+    pub user_counts: usize,
+    pub detections: usize,
+}
+
+/// 把 data 文件夹里的 `*_whitelist.json` 和 `*_user_info.json` 导入数据库，已有的记录会被覆盖
+pub fn migrate_json(
+    data_dir: &Path,
+    db_path: &Path,
+) -> Result<MigrateReport, Box<dyn std::error::Error>> {
+    let conn = open(db_path)?;
+    let mut conn = conn.lock().unwrap();
+    let tx = conn.transaction()?;
+    let mut report = MigrateReport::default();
+
+    for detector in ["long", "nailong"] {
+        let whitelist_path = data_dir.join(format!("{}_whitelist.json", detector));
+        if whitelist_path.exists() {
+            let whitelist: HashMap<i64, bool> =
+                serde_json::from_str(&std::fs::read_to_string(&whitelist_path)?)?;
+            for (group_id, enabled) in whitelist {
+                tx.execute(
+                    "INSERT INTO whitelist (detector, group_id, enabled) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (detector, group_id) DO UPDATE SET enabled = excluded.enabled",
+                    params![detector, group_id, enabled],
+                )?;
+                report.whitelist += 1;
+            }
+        }
+
+        let user_info_path = data_dir.join(format!("{}_user_info.json", detector));
+        if user_info_path.exists() {
+            let user_info: HashMap<i64, UserInfo> =
+                serde_json::from_str(&std::fs::read_to_string(&user_info_path)?)?;
+            for (user_id, info) in user_info {
+                for (group_id, times) in info.group_total_times {
+                    let last_timestamp = info.last_timestamp.get(&group_id).copied().unwrap_or(0);
+                    tx.execute(
+                        "INSERT INTO user_counts (detector, user_id, group_id, times, last_timestamp)
+                         VALUES (?1, ?2, ?3, ?4, ?5)
+                         ON CONFLICT (detector, user_id, group_id)
+                         DO UPDATE SET times = excluded.times, last_timestamp = excluded.last_timestamp",
+                        params![detector, user_id, group_id, times as i64, last_timestamp as i64],
+                    )?;
+                    report.user_counts += 1;
+                }
+
+                // 命中记录只补充数据库里没有的，重复导入不会多出记录
+                for hit in info.history {
+                    report.detections += tx.execute(
+                        "INSERT INTO detections (detector, user_id, group_id, timestamp, score, banned)
+                         SELECT ?1, ?2, ?3, ?4, ?5, ?6
+                         WHERE NOT EXISTS (
+                             SELECT 1 FROM detections
+                             WHERE detector = ?1 AND user_id = ?2 AND group_id = ?3 AND timestamp = ?4
+                         )",
+                        params![
+                            detector,
+                            user_id,
+                            hit.group_id,
+                            hit.timestamp as i64,
+                            hit.score as f64,
+                            hit.banned
+                        ],
+                    )?;
+                }
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(report)
+}
@@ -0,0 +1,223 @@
+use crate::storage::{HitRecord, Storage};
+use crate::template::Template;
+use kovi::chrono::{Datelike, Duration, Local, NaiveDate, TimeZone};
+use kovi::log::warn;
+use kovi::RuntimeBot;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 统计的时间范围
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Window {
+    Today,
+    Week,
+    All,
+}
+
+impl Window {
+    /// 命令后面的参数，空的时候为全部
+    pub(crate) fn parse(arg: &str) -> Option<Window> {
+        match arg.trim() {
+            "今日" | "今天" | "today" => Some(Window::Today),
+            "本周" | "这周" | "今週" | "week" => Some(Window::Week),
+            "" | "全部" | "总" | "全期間" | "all" => Some(Window::All),
+            _ => None,
+        }
+    }
+
+    /// 语言包里时间范围名称的 key
+    pub(crate) fn key(&self) -> &'static str {
+        match self {
+            Window::Today => "window.today",
+            Window::Week => "window.week",
+            Window::All => "window.all",
+        }
+    }
+
+    /// 起始日期，本周从周一开始；全部为 None
+    fn start(&self, today: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Window::Today => Some(today),
+            Window::Week => {
+                Some(today - Duration::days(today.weekday().num_days_from_monday() as i64))
+            }
+            Window::All => None,
+        }
+    }
+
+    /// 起始时间戳，按本地时间的零点计算；全部为 None
+    pub(crate) fn since(&self) -> Option<u64> {
+        let start = self.start(Local::now().date_naive())?;
+        Some(local_midnight(start))
+    }
+}
+
+/// 本地时间 date 零点的时间戳
+fn local_midnight(date: NaiveDate) -> u64 {
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+        .earliest()
+        .map_or(0, |v| v.timestamp().max(0) as u64)
+}
+
+/// 去掉次数为 0 的，按次数从多到少排序，次数相同的 QQ 号小的在前
+fn sort_ranking(mut counts: Vec<(i64, u64)>) -> Vec<(i64, u64)> {
+    counts.retain(|(_, times)| *times > 0);
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
+
+/// 本群发送次数从多到少排序，全部范围用累计次数，其他用命中记录
+pub(crate) fn ranking(storage: &dyn Storage, group_id: i64, window: Window) -> Vec<(i64, u64)> {
+    match window.since() {
+        Some(since) => ranking_since(&storage.history(Some(group_id), None, since)),
+        None => sort_ranking(storage.group_totals(group_id)),
+    }
+}
+
+/// 按命中记录统计每个用户的次数，从多到少排序
+pub(crate) fn ranking_since(history: &[HitRecord]) -> Vec<(i64, u64)> {
+    let mut counts: HashMap<i64, u64> = HashMap::new();
+    for hit in history {
+        *counts.entry(hit.user_id).or_insert(0) += 1;
+    }
+    sort_ranking(counts.into_iter().collect())
+}
+
+/// 最近 days 天每天的次数，按本地日期划分，最后一个是今天
+pub(crate) fn daily_counts(
+    storage: &dyn Storage,
+    group_id: Option<i64>,
+    user_id: Option<i64>,
+    days: usize,
+) -> Vec<u64> {
+    let today = Local::now().date_naive();
+    let first = today - Duration::days(days as i64 - 1);
+    let history = storage.history(group_id, user_id, local_midnight(first));
+    count_by_day(&history, first, days)
+}
+
+/// 从 first 开始 days 天里每天的命中次数，范围外的不计
+fn count_by_day(history: &[HitRecord], first: NaiveDate, days: usize) -> Vec<u64> {
+    let mut counts = vec![0; days];
+    for hit in history {
+        let Some(time) = Local.timestamp_opt(hit.timestamp as i64, 0).earliest() else {
+            continue;
+        };
+        let i = (time.date_naive() - first).num_days();
+        if (0..days as i64).contains(&i) {
+            counts[i as usize] += 1;
+        }
+    }
+    counts
+}
+
+/// 群名片，没有时用昵称，查询失败时用 QQ 号
+pub(crate) async fn member_name(bot: &RuntimeBot, group_id: i64, user_id: i64) -> String {
+    match bot.get_group_member_info(group_id, user_id, false).await {
+        Ok(v) => {
+            for key in ["card", "nickname"] {
+                if let Some(name) = v.data.get(key).and_then(|v| v.as_str()) {
+                    if !name.is_empty() {
+                        return name.to_string();
+                    }
+                }
+            }
+            user_id.to_string()
+        }
+        Err(err) => {
+            warn!("获取群成员信息失败: {:?}", err);
+            user_id.to_string()
+        }
+    }
+}
+
+/// 排行榜的输出格式
+///
+/// 可用的占位符：`{detector}` 检测器名称、`{window}` 时间范围、`{rank}` 名次、
+/// `{nickname}` 群名片或昵称、`{user}` QQ 号、`{count}` 次数；
+/// 旧的 `{name}` `{user_id}` `{times}` 仍然可用
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct RankTemplate {
+    pub(crate) header: Template,
+    pub(crate) line: Template,
+    pub(crate) empty: Template,
+}
+
+impl Default for RankTemplate {
+    fn default() -> Self {
+        Self {
+            header: Template::from("本群{detector}排行（{window}）"),
+            line: Template::from("{rank}. {nickname}：{count} 次"),
+            empty: Template::from("本群{window}还没有人发过{detector}哦~"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-01 是周一
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn hit(user_id: i64, timestamp: u64) -> HitRecord {
+        HitRecord {
+            user_id,
+            group_id: 1,
+            timestamp,
+            score: 0.9,
+            banned: false,
+        }
+    }
+
+    #[test]
+    fn window_start() {
+        assert_eq!(Window::Today.start(day(3)), Some(day(3)));
+        assert_eq!(Window::Week.start(day(3)), Some(day(1)));
+        assert_eq!(Window::Week.start(day(1)), Some(day(1)));
+        // 周日还算本周，周一才换新的一周
+        assert_eq!(Window::Week.start(day(7)), Some(day(1)));
+        assert_eq!(Window::Week.start(day(8)), Some(day(8)));
+        assert_eq!(Window::All.start(day(3)), None);
+    }
+
+    #[test]
+    fn parse_window() {
+        assert_eq!(Window::parse(""), Some(Window::All));
+        assert_eq!(Window::parse(" 今日 "), Some(Window::Today));
+        assert_eq!(Window::parse("week"), Some(Window::Week));
+        assert_eq!(Window::parse("明天"), None);
+    }
+
+    #[test]
+    fn ranking_order() {
+        let history = [hit(3, 0), hit(2, 0), hit(3, 0), hit(1, 0), hit(2, 0)];
+        // 次数相同时 QQ 号小的在前
+        assert_eq!(ranking_since(&history), vec![(2, 2), (3, 2), (1, 1)]);
+        assert_eq!(ranking_since(&[]), Vec::new());
+        assert_eq!(
+            sort_ranking(vec![(5, 0), (4, 1), (3, 7), (2, 1)]),
+            vec![(3, 7), (2, 1), (4, 1)]
+        );
+    }
+
+    #[test]
+    fn counts_by_local_day() {
+        let first = day(1);
+        let history = [
+            // 前一天最后一秒不计
+            hit(1, local_midnight(first) - 1),
+            hit(1, local_midnight(first)),
+            hit(1, local_midnight(day(2)) - 1),
+            hit(1, local_midnight(day(2))),
+            hit(2, local_midnight(day(3)) + 3600),
+            // 超出范围不计
+            hit(1, local_midnight(day(4))),
+        ];
+        assert_eq!(count_by_day(&history, first, 3), vec![2, 1, 1]);
+        assert_eq!(count_by_day(&[], first, 2), vec![0, 0]);
+    }
+}
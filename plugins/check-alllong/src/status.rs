@@ -0,0 +1,156 @@
+use crate::detector::Detector;
+use crate::i18n::i18n;
+use crate::maintenance::{mode, mode_cmd};
+use crate::permission::permissions;
+use crate::schedule::schedules;
+use crate::stats::Window;
+use crate::template::Vars;
+use kovi::{AllMsgEvent, RuntimeBot};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct StatusConfig {
+    pub(crate) help_cmd: String,   // 列出所有命令
+    pub(crate) status_cmd: String, // 查看本群的检测状态
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            help_cmd: ".lohelp".to_string(),
+            status_cmd: ".lostatus".to_string(),
+        }
+    }
+}
+
+/// 帮助和状态命令
+pub(crate) struct Status {
+    config: StatusConfig,
+    started: Instant,
+    detectors: Vec<Detector>,
+}
+
+impl Status {
+    pub(crate) fn new(config: StatusConfig, detectors: Vec<Detector>) -> Self {
+        Self {
+            config,
+            started: Instant::now(),
+            detectors,
+        }
+    }
+
+    pub(crate) fn handle(&self, e: &AllMsgEvent, bot: &RuntimeBot) {
+        let Some(group_id) = e.group_id else {
+            return;
+        };
+        let Some(text) = e.borrow_text() else {
+            return;
+        };
+
+        let text = text.trim();
+        let lang = i18n().lang(Some(group_id));
+        if text == self.config.help_cmd {
+            if permissions().check(bot, e, "help") {
+                e.reply(self.help(&lang));
+            }
+        } else if text == self.config.status_cmd && permissions().check(bot, e, "status") {
+            e.reply(self.status(&lang, group_id));
+        }
+    }
+
+    /// 每个检测器配置的命令，以及通用的命令
+    fn help(&self, lang: &str) -> String {
+        let text = |key: &str, cmd: &str, detector: &str| {
+            let vars = Vars::default().set("cmd", cmd).set("detector", detector);
+            i18n().text(lang, key, &vars)
+        };
+
+        let mut lines = vec![text("help.title", "", "")];
+        for detector in &self.detectors {
+            let config = &detector.config;
+            let name = i18n().detector_name(lang, &detector.name);
+            lines.push(text("help.detector", "", &name));
+            lines.push(text("help.my_times", &config.my_times_cmd, &name));
+            lines.push(text("help.rank", &detector.rank_cmd(), &name));
+            lines.push(text("help.start", &config.start_cmd, &name));
+            lines.push(text("help.stop", &config.stop_cmd, &name));
+            lines.push(text("help.stats", &detector.stats_cmd(), &name));
+        }
+
+        // 识别图片的命令是共用的
+        if let Some(detector) = self.detectors.first() {
+            lines.push(text("help.common", "", ""));
+            lines.push(text(
+                "help.check",
+                &detector.config.reply_output_img_cmd,
+                "",
+            ));
+        }
+        lines.push(text("help.status", &self.config.status_cmd, ""));
+        lines.push(text("help.help", &self.config.help_cmd, ""));
+        lines.push(text("help.lang", i18n().lang_cmd(), ""));
+        lines.push(text("help.mode", mode_cmd(), ""));
+        lines.join("\n")
+    }
+
+    /// 运行时间，以及每个检测器在本群的开关、时间表、阈值、处理方式、模型和今日次数
+    fn status(&self, lang: &str, group_id: i64) -> String {
+        let secs = self.started.elapsed().as_secs();
+        let vars = Vars::default()
+            .set("days", secs / 86400)
+            .set("hours", secs % 86400 / 3600)
+            .set("minutes", secs % 3600 / 60);
+        let uptime = i18n().text(lang, "status.uptime", &vars);
+        let mut lines =
+            vec![i18n().text(lang, "status.title", &Vars::default().set("uptime", uptime))];
+
+        let vars = Vars::default().set("mode", i18n().text(lang, mode().key(), &Vars::default()));
+        lines.push(i18n().text(lang, "status.global", &vars));
+
+        let since = Window::Today.since().unwrap_or(0);
+        for detector in &self.detectors {
+            let config = &detector.config;
+            let state = if detector.storage.is_enabled(group_id) {
+                "status.enabled"
+            } else {
+                "status.disabled"
+            };
+            let vars = Vars::default()
+                .set("detector", i18n().detector_name(lang, &detector.name))
+                .set("state", i18n().text(lang, state, &Vars::default()))
+                .set("version", &detector.model_version);
+            lines.push(i18n().text(lang, "status.detector", &vars));
+
+            if let Some(action) = schedules().action(group_id, &detector.name) {
+                let vars = Vars::default()
+                    .set("action", i18n().text(lang, action.key(), &Vars::default()));
+                lines.push(i18n().text(lang, "status.schedule", &vars));
+            }
+
+            let vars = Vars::default().set("trigger", config.trigger_text());
+            lines.push(i18n().text(lang, "status.threshold", &vars));
+
+            let mut actions = vec![i18n().text(lang, "status.action_reply", &Vars::default())];
+            if config.is_delete_message {
+                actions.push(i18n().text(lang, "status.action_delete", &Vars::default()));
+            }
+            if config.ban_duration > 0 {
+                let vars = Vars::default()
+                    .set("cooldown", config.ban_cooldown)
+                    .set("ban_minutes", config.ban_duration.div_ceil(60));
+                actions.push(i18n().text(lang, "status.action_ban", &vars));
+            }
+            let separator = i18n().text(lang, "status.separator", &Vars::default());
+            let vars = Vars::default().set("actions", actions.join(&separator));
+            lines.push(i18n().text(lang, "status.mode", &vars));
+
+            let history = detector.storage.history(Some(group_id), None, since);
+            let vars = Vars::default()
+                .set("count", history.len())
+                .set("bans", history.iter().filter(|v| v.banned).count());
+            lines.push(i18n().text(lang, "status.today", &vars));
+        }
+        lines.join("\n")
+    }
+}
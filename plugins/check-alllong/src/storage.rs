@@ -0,0 +1,322 @@
+use crate::store::JsonStore;
+use crate::UserInfo;
+use kovi::log::info;
+use kovi::tokio;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 清理过期命中记录的间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// 一次命中记录
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct HitRecord {
+    pub(crate) user_id: i64,
+    pub(crate) group_id: i64,
+    pub(crate) timestamp: u64,
+    pub(crate) score: f32,
+    pub(crate) banned: bool,
+}
+
+/// 记录命中的结果
+pub(crate) struct Recorded {
+    /// 该用户在本群上一次命中的时间戳
+    pub(crate) last_timestamp: Option<u64>,
+    /// 这次命中的编号，写入失败时为 None
+    pub(crate) id: Option<i64>,
+}
+
+/// 一个检测器的白名单和发送统计，JSON 文件和 SQLite 两种实现
+pub(crate) trait Storage: Send + Sync {
+    /// 本群是否开启检测
+    fn is_enabled(&self, group_id: i64) -> bool;
+
+    fn set_enabled(&self, group_id: i64, enabled: bool);
+
+    /// 开启检测的群
+    fn enabled_groups(&self) -> Vec<i64>;
+
+    /// 记录一次命中
+    fn record(&self, user_id: i64, group_id: i64, timestamp: u64, score: f32) -> Recorded;
+
+    /// 把编号为 id 的命中标记为已禁言
+    fn mark_banned(&self, user_id: i64, group_id: i64, id: i64);
+
+    /// 用户在本群的次数和总次数，在所有群都没有记录（或都已清空）时返回 None
+    fn user_times(&self, user_id: i64, group_id: i64) -> Option<(u64, u64)>;
+
+    /// 本群每个用户的累计次数
+    fn group_totals(&self, group_id: i64) -> Vec<(i64, u64)>;
+
+    /// since 之后的命中记录，group_id 和 user_id 为 None 时不过滤，按时间排序
+    fn history(&self, group_id: Option<i64>, user_id: Option<i64>, since: u64) -> Vec<HitRecord>;
+
+    /// 清空用户在本群的次数和命中记录
+    fn reset_user(&self, user_id: i64, group_id: i64);
+
+    /// 清空本群所有用户的次数和命中记录
+    fn reset_group(&self, group_id: i64);
+
+    /// 把用户在本群的次数改为 times，总次数跟着变化
+    fn set_times(&self, user_id: i64, group_id: i64, times: u64);
+
+    /// 删除 before 之前的命中记录，返回删除的条数；累计次数不受影响
+    fn prune(&self, before: u64) -> usize;
+}
+
+pub(crate) struct JsonStorage {
+    pub(crate) whitelist: Arc<JsonStore<HashMap<i64, bool>>>,
+    pub(crate) user_info: Arc<JsonStore<HashMap<i64, UserInfo>>>,
+}
+
+impl Storage for JsonStorage {
+    fn is_enabled(&self, group_id: i64) -> bool {
+        self.whitelist
+            .read()
+            .get(&group_id)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn set_enabled(&self, group_id: i64, enabled: bool) {
+        self.whitelist.write().insert(group_id, enabled);
+    }
+
+    fn enabled_groups(&self) -> Vec<i64> {
+        self.whitelist
+            .read()
+            .iter()
+            .filter(|(_, enabled)| **enabled)
+            .map(|(group_id, _)| *group_id)
+            .collect()
+    }
+
+    fn record(&self, user_id: i64, group_id: i64, timestamp: u64, score: f32) -> Recorded {
+        let mut user_info = self.user_info.write();
+        let user_data = user_info.entry(user_id).or_insert_with(|| UserInfo {
+            total_times: 0,
+            group_total_times: HashMap::new(),
+            last_timestamp: HashMap::new(),
+            history: Vec::new(),
+            last_hit_id: 0,
+        });
+
+        let last_timestamp = user_data.last_timestamp.get(&group_id).copied();
+        let id = user_data.update_time(group_id, timestamp, score);
+        Recorded {
+            last_timestamp,
+            id: Some(id),
+        }
+    }
+
+    fn mark_banned(&self, user_id: i64, group_id: i64, id: i64) {
+        if let Some(user_data) = self.user_info.write().get_mut(&user_id) {
+            if let Some(hit) = user_data
+                .history
+                .iter_mut()
+                .rev()
+                .find(|v| v.group_id == group_id && v.id == id)
+            {
+                hit.banned = true;
+            }
+        }
+    }
+
+    fn user_times(&self, user_id: i64, group_id: i64) -> Option<(u64, u64)> {
+        let user_info = self.user_info.read();
+        let user_data = user_info.get(&user_id)?;
+        // 和 SQLite 一致：清空后不留空记录
+        if user_data.group_total_times.is_empty() {
+            return None;
+        }
+        let group_times = user_data
+            .group_total_times
+            .get(&group_id)
+            .copied()
+            .unwrap_or(0);
+        Some((group_times, user_data.total_times))
+    }
+
+    fn group_totals(&self, group_id: i64) -> Vec<(i64, u64)> {
+        self.user_info
+            .read()
+            .iter()
+            .filter_map(|(user_id, user_data)| {
+                let times = user_data.group_total_times.get(&group_id)?;
+                Some((*user_id, *times))
+            })
+            .collect()
+    }
+
+    fn history(&self, group_id: Option<i64>, user_id: Option<i64>, since: u64) -> Vec<HitRecord> {
+        let user_info = self.user_info.read();
+        let mut records: Vec<HitRecord> = user_info
+            .iter()
+            .filter(|(id, _)| user_id.is_none_or(|v| v == **id))
+            .flat_map(|(id, user_data)| {
+                user_data.history.iter().map(|hit| HitRecord {
+                    user_id: *id,
+                    group_id: hit.group_id,
+                    timestamp: hit.timestamp,
+                    score: hit.score,
+                    banned: hit.banned,
+                })
+            })
+            .filter(|v| v.timestamp >= since && group_id.is_none_or(|id| id == v.group_id))
+            .collect();
+        records.sort_by_key(|v| v.timestamp);
+        records
+    }
+
+    fn reset_user(&self, user_id: i64, group_id: i64) {
+        if let Some(user_data) = self.user_info.write().get_mut(&user_id) {
+            user_data.reset_group(group_id);
+        }
+    }
+
+    fn reset_group(&self, group_id: i64) {
+        for user_data in self.user_info.write().values_mut() {
+            user_data.reset_group(group_id);
+        }
+    }
+
+    fn set_times(&self, user_id: i64, group_id: i64, times: u64) {
+        let mut user_info = self.user_info.write();
+        let user_data = user_info.entry(user_id).or_insert_with(|| UserInfo {
+            total_times: 0,
+            group_total_times: HashMap::new(),
+            last_timestamp: HashMap::new(),
+            history: Vec::new(),
+            last_hit_id: 0,
+        });
+        let old = user_data
+            .group_total_times
+            .insert(group_id, times)
+            .unwrap_or(0);
+        user_data.total_times = (user_data.total_times + times).saturating_sub(old);
+    }
+
+    fn prune(&self, before: u64) -> usize {
+        // 先用读锁检查，没有过期记录时不标记为待保存
+        let expired = self
+            .user_info
+            .read()
+            .values()
+            .any(|v| v.history.iter().any(|hit| hit.timestamp < before));
+        if !expired {
+            return 0;
+        }
+
+        let mut removed = 0;
+        for user_data in self.user_info.write().values_mut() {
+            let len = user_data.history.len();
+            user_data.history.retain(|hit| hit.timestamp >= before);
+            removed += len - user_data.history.len();
+        }
+        removed
+    }
+}
+
+/// 后台定期删除超过 days 天的命中记录，days 为 0 时不清理
+pub(crate) fn spawn_pruner(storages: Vec<Arc<dyn Storage>>, days: u64) {
+    if days == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let before = now.saturating_sub(days * 86400);
+            let removed: usize = storages.iter().map(|v| v.prune(before)).sum();
+            if removed > 0 {
+                info!("已清理 {} 条超过 {} 天的命中记录", removed, days);
+            }
+
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{Autosaver, StoreConfig};
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "check-alllong-storage-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// 两种实现对同一串操作的结果要一致
+    fn check_storage(storage: &dyn Storage) {
+        assert_eq!(storage.user_times(1, 100), None);
+
+        assert_eq!(storage.record(1, 100, 10, 0.9).last_timestamp, None);
+        let second = storage.record(1, 100, 10, 0.8);
+        assert_eq!(second.last_timestamp, Some(10));
+        storage.record(1, 200, 20, 0.9);
+        assert_eq!(storage.user_times(1, 100), Some((2, 3)));
+        assert_eq!(storage.user_times(1, 200), Some((1, 3)));
+
+        // 同一秒的两次命中只标记指定的那一次
+        storage.mark_banned(1, 100, second.id.unwrap());
+        let banned: Vec<bool> = storage
+            .history(Some(100), Some(1), 0)
+            .iter()
+            .map(|v| v.banned)
+            .collect();
+        assert_eq!(banned.iter().filter(|v| **v).count(), 1);
+        assert_eq!(banned.len(), 2);
+
+        storage.reset_user(1, 100);
+        assert_eq!(storage.user_times(1, 100), Some((0, 1)));
+        assert!(storage.history(Some(100), None, 0).is_empty());
+
+        storage.reset_group(200);
+        assert_eq!(storage.user_times(1, 200), None);
+
+        storage.set_times(1, 100, 5);
+        storage.set_times(1, 200, 2);
+        assert_eq!(storage.user_times(1, 100), Some((5, 7)));
+        storage.set_times(1, 100, 1);
+        assert_eq!(storage.user_times(1, 100), Some((1, 3)));
+
+        let mut totals = storage.group_totals(100);
+        totals.sort();
+        assert_eq!(totals, vec![(1, 1)]);
+    }
+
+    #[test]
+    fn json_storage() {
+        let dir = temp_dir("json");
+        let autosaver = Autosaver::new(StoreConfig::default());
+        let storage = JsonStorage {
+            whitelist: autosaver.load(HashMap::new(), dir.join("whitelist.json")),
+            user_info: autosaver.load(HashMap::new(), dir.join("user_info.json")),
+        };
+        check_storage(&storage);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_storage() {
+        let dir = temp_dir("sqlite");
+        let conn = crate::sqlite::open(&dir.join("data.db")).unwrap();
+        let storage = crate::sqlite::SqliteStorage::new(conn, "long");
+        check_storage(&storage);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,355 @@
+use kovi::log::{error, info, warn};
+use kovi::serde_json;
+use kovi::tokio::{self, sync::Notify};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub(crate) struct StoreConfig {
+    pub(crate) autosave_interval: u64, // 定期保存的间隔，秒
+    pub(crate) autosave_delay: u64,    // 数据修改后多久保存，秒
+    #[serde(default = "default_backup_count")]
+    pub(crate) backup_count: usize, // 每个文件保留的备份数
+    #[serde(default = "default_backend")]
+    pub(crate) backend: String, // json 或 sqlite，sqlite 需要开启 sqlite feature
+    #[serde(default = "default_sqlite_path")]
+    pub(crate) sqlite_path: String, // 数据库文件，相对于 data 文件夹
+    #[serde(default = "default_history_days")]
+    pub(crate) history_days: u64, // 命中记录保留的天数，0 为永久保留
+}
+
+fn default_backup_count() -> usize {
+    3
+}
+
+fn default_backend() -> String {
+    "json".to_string()
+}
+
+fn default_sqlite_path() -> String {
+    "data.db".to_string()
+}
+
+fn default_history_days() -> u64 {
+    90
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            autosave_interval: 300,
+            autosave_delay: 10,
+            backup_count: default_backup_count(),
+            backend: default_backend(),
+            sqlite_path: default_sqlite_path(),
+            history_days: default_history_days(),
+        }
+    }
+}
+
+/// data 文件夹里的一份 JSON 数据，通过 write 修改后会标记为待保存
+pub(crate) struct JsonStore<T> {
+    data: RwLock<T>,
+    path: PathBuf,
+    dirty: AtomicBool,
+    notify: Arc<Notify>,
+    backup_count: usize,
+}
+
+impl<T> JsonStore<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.data.read().unwrap()
+    }
+
+    /// 拿到写锁就视为修改过
+    ///
+    /// 先拿锁再标记：保存时在清除标记之后才拿读锁，这样要么读到这次的修改，
+    /// 要么标记留到下一次保存，不会漏掉
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let guard = self.data.write().unwrap();
+        self.dirty.store(true, Ordering::Release);
+        self.notify.notify_one();
+        guard
+    }
+
+    /// 立即把修改写入文件，不等自动保存
+    pub(crate) fn flush(&self) {
+        self.save_if_dirty();
+    }
+}
+
+trait Persist: Send + Sync {
+    fn save_if_dirty(&self);
+}
+
+impl<T> Persist for JsonStore<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn save_if_dirty(&self) {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return;
+        }
+
+        let data = self.data.read().unwrap();
+        if let Err(err) = save_atomic(&*data, &self.path, self.backup_count) {
+            error!("保存 {:?} 失败: {}", self.path, err);
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// 管理所有 JsonStore，后台定期把修改过的数据写回文件
+pub(crate) struct Autosaver {
+    config: StoreConfig,
+    stores: Mutex<Vec<Arc<dyn Persist>>>,
+    notify: Arc<Notify>,
+}
+
+impl Autosaver {
+    pub(crate) fn new(config: StoreConfig) -> Self {
+        Self {
+            config,
+            stores: Mutex::new(Vec::new()),
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    pub(crate) fn load<T>(&self, default_value: T, path: PathBuf) -> Arc<JsonStore<T>>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let data = load_or_recover(default_value, &path, self.config.backup_count);
+        let store = Arc::new(JsonStore {
+            data: RwLock::new(data),
+            path,
+            dirty: AtomicBool::new(false),
+            notify: self.notify.clone(),
+            backup_count: self.config.backup_count,
+        });
+        self.stores.lock().unwrap().push(store.clone());
+        store
+    }
+
+    pub(crate) fn save_all(&self) {
+        let stores = self.stores.lock().unwrap().clone();
+        for store in stores {
+            store.save_if_dirty();
+        }
+    }
+
+    /// 每隔 autosave_interval 保存一次，数据修改后等 autosave_delay 再保存一次
+    pub(crate) fn spawn(self: &Arc<Self>) {
+        let autosaver = self.clone();
+        let config = self.config.clone();
+        tokio::spawn(async move {
+            info!(
+                "自动保存已开启，间隔 {} 秒，修改后 {} 秒保存",
+                config.autosave_interval, config.autosave_delay
+            );
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(config.autosave_interval)) => {}
+                    _ = autosaver.notify.notified() => {
+                        tokio::time::sleep(Duration::from_secs(config.autosave_delay)).await;
+                    }
+                }
+
+                let autosaver = autosaver.clone();
+                if let Err(err) = tokio::task::spawn_blocking(move || autosaver.save_all()).await {
+                    error!("自动保存失败: {}", err);
+                }
+            }
+        });
+    }
+}
+
+fn backup_path(path: &Path, i: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".bak.{}", i));
+    PathBuf::from(name)
+}
+
+/// 先写临时文件再改名，写到一半崩溃也不会损坏原文件；覆盖前把原文件轮换为备份
+fn save_atomic<T: Serialize>(
+    data: &T,
+    path: &Path,
+    backup_count: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(serde_json::to_string_pretty(data)?.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    if backup_count > 0 && path.exists() {
+        for i in (1..backup_count).rev() {
+            let from = backup_path(path, i);
+            if from.exists() {
+                fs::rename(&from, backup_path(path, i + 1))?;
+            }
+        }
+        fs::copy(path, backup_path(path, 1))?;
+    }
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn std::error::Error>> {
+    let data = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// 文件损坏时依次尝试备份，都不行再用默认值，不会 panic
+fn load_or_recover<T>(default_value: T, path: &Path, backup_count: usize) -> T
+where
+    T: Serialize + DeserializeOwned,
+{
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(err) = save_atomic(&default_value, path, 0) {
+            error!("创建 {:?} 失败: {}", path, err);
+        }
+        return default_value;
+    }
+
+    let err = match read_json(path) {
+        Ok(v) => return v,
+        Err(err) => err,
+    };
+    warn!("{:?} 读取失败: {}，尝试从备份恢复", path, err);
+
+    // 留一份损坏的文件，方便排查
+    let mut corrupt_path = path.as_os_str().to_owned();
+    corrupt_path.push(".corrupt");
+    if let Err(err) = fs::copy(path, PathBuf::from(corrupt_path)) {
+        error!("备份损坏的 {:?} 失败: {}", path, err);
+    }
+
+    let mut recovered = None;
+    for i in 1..=backup_count {
+        let backup = backup_path(path, i);
+        if !backup.exists() {
+            continue;
+        }
+        match read_json(&backup) {
+            Ok(v) => {
+                warn!("{:?} 已从备份 {:?} 恢复", path, backup);
+                recovered = Some(v);
+                break;
+            }
+            Err(err) => warn!("备份 {:?} 也无法读取: {}", backup, err),
+        }
+    }
+    let data = recovered.unwrap_or_else(|| {
+        warn!("{:?} 没有可用的备份，使用默认值", path);
+        default_value
+    });
+
+    // 直接覆盖损坏的文件，不轮换备份，免得损坏的文件挤掉可用的备份
+    if let Err(err) = save_atomic(&data, path, 0) {
+        error!("写回恢复的 {:?} 失败: {}", path, err);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "check-alllong-store-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, data: &str) {
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn recovers_from_first_backup() {
+        let dir = temp_dir("bak1");
+        let path = dir.join("data.json");
+        write(&path, "[1, 2");
+        write(&backup_path(&path, 1), "[1, 2, 3]");
+        write(&backup_path(&path, 2), "[1]");
+
+        let data: Vec<i32> = load_or_recover(Vec::new(), &path, 3);
+        assert_eq!(data, vec![1, 2, 3]);
+        // 恢复的数据写回原文件，损坏的文件另存一份
+        assert_eq!(read_json::<Vec<i32>>(&path).unwrap(), vec![1, 2, 3]);
+        assert!(dir.join("data.json.corrupt").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn falls_back_to_second_backup() {
+        let dir = temp_dir("bak2");
+        let path = dir.join("data.json");
+        write(&path, "");
+        write(&backup_path(&path, 1), "{");
+        write(&backup_path(&path, 2), "[7]");
+
+        let data: Vec<i32> = load_or_recover(Vec::new(), &path, 3);
+        assert_eq!(data, vec![7]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rotation_keeps_backup_count() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("data.json");
+        for i in 0..5 {
+            save_atomic(&vec![i], &path, 2).unwrap();
+        }
+
+        assert_eq!(read_json::<Vec<i32>>(&path).unwrap(), vec![4]);
+        assert_eq!(
+            read_json::<Vec<i32>>(&backup_path(&path, 1)).unwrap(),
+            vec![3]
+        );
+        assert_eq!(
+            read_json::<Vec<i32>>(&backup_path(&path, 2)).unwrap(),
+            vec![2]
+        );
+        assert!(!backup_path(&path, 3).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn save_leaves_no_tmp() {
+        let dir = temp_dir("tmp");
+        let path = dir.join("data.json");
+        save_atomic(&vec![1], &path, 3).unwrap();
+        save_atomic(&vec![2], &path, 3).unwrap();
+
+        let names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|v| v.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(names.iter().all(|v| !v.ends_with(".tmp")), "{:?}", names);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,148 @@
+use kovi::AllMsgEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+
+/// 回复模板，可以写一条，也可以写多条每次随机选一条
+///
+/// 文字里的 `{user}` `{nickname}` `{score}` `{count}` `{ban_minutes}` `{detector}` 等
+/// 占位符会被替换，没有对应值的占位符原样保留
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub(crate) enum Template {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<&str> for Template {
+    fn from(v: &str) -> Self {
+        Template::One(v.to_string())
+    }
+}
+
+impl Template {
+    /// 随机选一条，没有内容时为空字符串
+    fn pick(&self) -> &str {
+        match self {
+            Template::One(v) => v,
+            Template::Many(v) if v.is_empty() => "",
+            Template::Many(v) => {
+                // 每个 RandomState 的密钥都不同，空输入的哈希值就是一个随机数；
+                // 只是挑一条回复，不需要均匀和不可预测，不为此引入 rand
+                let i = RandomState::new().build_hasher().finish() as usize % v.len();
+                &v[i]
+            }
+        }
+    }
+
+    pub(crate) fn render(&self, vars: &Vars) -> String {
+        vars.fill(self.pick())
+    }
+}
+
+/// 占位符的值
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Vars {
+    values: HashMap<&'static str, String>,
+}
+
+impl Vars {
+    /// 发送者的 QQ 号、群名片或昵称，以及检测器名称
+    pub(crate) fn for_event(e: &AllMsgEvent, detector: &str) -> Self {
+        let nickname = [e.sender.card.as_deref(), e.sender.nickname.as_deref()]
+            .into_iter()
+            .flatten()
+            .find(|v| !v.is_empty())
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| e.user_id.to_string());
+
+        Vars::default()
+            .set("user", e.user_id)
+            .set("nickname", nickname)
+            .set("detector", detector)
+    }
+
+    pub(crate) fn set(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.values.insert(key, value.to_string());
+        self
+    }
+
+    fn fill(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let value = after
+                .find('}')
+                .and_then(|end| Some((self.values.get(&after[..end])?, end)));
+            match value {
+                Some((value, end)) => {
+                    out.push_str(value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kovi::serde_json;
+
+    #[test]
+    fn fills_placeholders() {
+        let vars = Vars::default()
+            .set("user", 123)
+            .set("score", "0.90")
+            .set("count", 2);
+        assert_eq!(
+            Template::from("{user} 发了龙图，相似度 {score}，第 {count} 次").render(&vars),
+            "123 发了龙图，相似度 0.90，第 2 次"
+        );
+        assert_eq!(Template::from("{user}{user}").render(&vars), "123123");
+        assert_eq!(Template::from("没有占位符").render(&vars), "没有占位符");
+    }
+
+    #[test]
+    fn keeps_unknown_placeholders() {
+        let vars = Vars::default().set("user", 123);
+        assert_eq!(
+            Template::from("{unknown} {user}").render(&vars),
+            "{unknown} 123"
+        );
+        assert_eq!(Template::from("{{user}}").render(&vars), "{123}");
+        assert_eq!(Template::from("{user").render(&vars), "{user");
+        assert_eq!(Template::from("}{").render(&vars), "}{");
+    }
+
+    #[test]
+    fn one_or_many() {
+        let one: Template = serde_json::from_str(r#""龙图""#).unwrap();
+        assert!(matches!(one, Template::One(ref v) if v == "龙图"));
+        let many: Template = serde_json::from_str(r#"["a", "b"]"#).unwrap();
+        assert!(matches!(many, Template::Many(ref v) if v.len() == 2));
+        assert!(serde_json::from_str::<Template>("1").is_err());
+
+        let vars = Vars::default();
+        assert_eq!(Template::Many(Vec::new()).render(&vars), "");
+        assert_eq!(Template::Many(vec!["a".to_string()]).render(&vars), "a");
+    }
+
+    #[test]
+    fn picks_every_entry() {
+        let template = Template::Many(vec!["a".to_string(), "b".to_string()]);
+        let vars = Vars::default();
+        let picked: Vec<String> = (0..200).map(|_| template.render(&vars)).collect();
+        assert!(picked.iter().any(|v| v == "a"));
+        assert!(picked.iter().any(|v| v == "b"));
+    }
+}
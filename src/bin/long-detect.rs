@@ -0,0 +1,299 @@
+use long_detector_core::builtin::{builtin_detector, DetectConfig};
+use long_detector_core::files::collect_files;
+use long_detector_core::{annotate, decode_bytes, Detection, Model, Thresholds};
+use serde_json::json;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "用法: long-detect [选项] <文件或文件夹>...
+
+选项:
+  -d, --detector <long|nailong|all>  使用的检测器，默认 all
+  -c, --config-dir <目录>            从目录读取 long_config.json / nailong_config.json
+  -f, --format <table|json|csv>      输出格式，默认 table
+  -o, --annotate <目录>              把命中的图片画框后保存到目录
+  -h, --help                         显示帮助
+
+有图片命中时退出码为 1，参数或读取错误时为 2";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+struct Args {
+    detectors: Vec<String>,
+    config_dir: Option<PathBuf>,
+    format: Format,
+    annotate_dir: Option<PathBuf>,
+    paths: Vec<PathBuf>,
+}
+
+struct NamedDetector {
+    name: &'static str,
+    model: Model,
+    thresholds: Thresholds,
+}
+
+struct Row {
+    file: PathBuf,
+    detector: &'static str,
+    detection: Detection,
+}
+
+fn main() -> ExitCode {
+    if std::env::args().any(|v| v == "-h" || v == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let detectors = match load_detectors(&args) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("加载检测器失败: {}", err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut files = Vec::new();
+    for path in &args.paths {
+        files.extend(collect_files(path, |path, err| {
+            eprintln!("{}: {}", path.display(), err)
+        }));
+    }
+    files.sort();
+    files.dedup();
+
+    if let Some(dir) = &args.annotate_dir {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            eprintln!("创建输出目录失败: {}", err);
+            return ExitCode::from(2);
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut has_error = false;
+    let mut output_names = HashSet::new();
+    for file in &files {
+        let data = match std::fs::read(file) {
+            Ok(v) => v,
+            Err(err) => {
+                eprintln!("{}: {}", file.display(), err);
+                has_error = true;
+                continue;
+            }
+        };
+        let img = match decode_bytes(&data) {
+            Ok(v) => v,
+            Err(err) => {
+                eprintln!("{}: 不是图片 ({})", file.display(), err);
+                continue;
+            }
+        };
+
+        for detector in &detectors {
+            let detection = match detector.model.detect(&img, &detector.thresholds) {
+                Ok(v) => v,
+                Err(err) => {
+                    eprintln!("{}: {} 推理失败: {}", file.display(), detector.name, err);
+                    has_error = true;
+                    continue;
+                }
+            };
+
+            if let (Some(dir), Some(_)) = (&args.annotate_dir, detection.hit()) {
+                let output_path = dir.join(output_name(file, detector.name, &mut output_names));
+                if let Err(err) = annotate(&img, &detection).save(&output_path) {
+                    eprintln!("{}: 保存失败: {}", output_path.display(), err);
+                }
+            }
+
+            rows.push(Row {
+                file: file.clone(),
+                detector: detector.name,
+                detection,
+            });
+        }
+    }
+
+    print_rows(&rows, args.format);
+
+    if rows.iter().any(|v| v.detection.hit().is_some()) {
+        ExitCode::from(1)
+    } else if has_error {
+        ExitCode::from(2)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn parse_args(mut iter: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut args = Args {
+        detectors: vec!["long".to_string(), "nailong".to_string()],
+        config_dir: None,
+        format: Format::Table,
+        annotate_dir: None,
+        paths: Vec::new(),
+    };
+
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| iter.next().ok_or(format!("{} 缺少参数", name));
+        match arg.as_str() {
+            "-d" | "--detector" => {
+                args.detectors = match value(&arg)?.as_str() {
+                    "all" => vec!["long".to_string(), "nailong".to_string()],
+                    v @ ("long" | "nailong") => vec![v.to_string()],
+                    v => return Err(format!("未知的检测器: {}", v)),
+                }
+            }
+            "-c" | "--config-dir" => args.config_dir = Some(PathBuf::from(value(&arg)?)),
+            "-f" | "--format" => {
+                args.format = match value(&arg)?.as_str() {
+                    "table" => Format::Table,
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    v => return Err(format!("未知的输出格式: {}", v)),
+                }
+            }
+            "-o" | "--annotate" => args.annotate_dir = Some(PathBuf::from(value(&arg)?)),
+            v if v.starts_with('-') => return Err(format!("未知的选项: {}", v)),
+            _ => args.paths.push(PathBuf::from(&arg)),
+        }
+    }
+
+    if args.paths.is_empty() {
+        return Err("没有指定文件或文件夹".to_string());
+    }
+
+    Ok(args)
+}
+
+fn load_detectors(args: &Args) -> Result<Vec<NamedDetector>, Box<dyn std::error::Error>> {
+    let mut detectors = Vec::new();
+    for name in &args.detectors {
+        let (name, (model, labels, default_config)) = match name.as_str() {
+            "long" => ("long", builtin_detector("long").unwrap()),
+            _ => ("nailong", builtin_detector("nailong").unwrap()),
+        };
+
+        let config = match &args.config_dir {
+            Some(dir) => DetectConfig::from_file_or(
+                &dir.join(format!("{}_config.json", name)),
+                default_config,
+            )?,
+            None => default_config,
+        };
+
+        detectors.push(NamedDetector {
+            name,
+            model: Model::new(model, labels.to_vec())?,
+            thresholds: config.thresholds(labels),
+        });
+    }
+    Ok(detectors)
+}
+
+/// 用图片的路径起名，例如 imgs/a/1.png 为 imgs_a_1-long.png，不同文件夹里同名的图片不会互相覆盖
+fn output_name(file: &Path, detector: &str, used: &mut HashSet<String>) -> String {
+    let stem: Vec<_> = file
+        .with_extension("")
+        .components()
+        .filter_map(|v| match v {
+            Component::Normal(v) => Some(v.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    let base = format!("{}-{}", stem.join("_"), detector);
+
+    let mut name = format!("{}.png", base);
+    let mut i = 2;
+    while !used.insert(name.clone()) {
+        name = format!("{}-{}.png", base, i);
+        i += 1;
+    }
+    name
+}
+
+fn print_rows(rows: &[Row], format: Format) {
+    match format {
+        Format::Table => {
+            println!(
+                "{:<8} {:<8} {:<8} {:<8} 文件",
+                "检测器", "命中", "分数", "类别"
+            );
+            for row in rows {
+                let hit = row.detection.hit();
+                println!(
+                    "{:<8} {:<8} {:<8.4} {:<8} {}",
+                    row.detector,
+                    if hit.is_some() { "yes" } else { "no" },
+                    row.detection.max_target_score,
+                    hit.map(|v| v.label).unwrap_or("-"),
+                    row.file.display()
+                );
+            }
+        }
+        Format::Json => {
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|row| {
+                    let boxes: Vec<_> = row
+                        .detection
+                        .boxes
+                        .iter()
+                        .map(|v| {
+                            json!({
+                                "label": v.label,
+                                "score": v.score,
+                                "is_target": v.is_target,
+                                "trigger": v.trigger,
+                                "box": [v.bbox.x1, v.bbox.y1, v.bbox.x2, v.bbox.y2],
+                            })
+                        })
+                        .collect();
+                    json!({
+                        "file": row.file.display().to_string(),
+                        "detector": row.detector,
+                        "hit": row.detection.hit().is_some(),
+                        "max_target_score": row.detection.max_target_score,
+                        "boxes": boxes,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&rows).unwrap());
+        }
+        Format::Csv => {
+            println!("file,detector,hit,max_target_score,label");
+            for row in rows {
+                let hit = row.detection.hit();
+                println!(
+                    "{},{},{},{:.4},{}",
+                    csv_field(&row.file.display().to_string()),
+                    row.detector,
+                    hit.is_some(),
+                    row.detection.max_target_score,
+                    hit.map(|v| v.label).unwrap_or("")
+                );
+            }
+        }
+    }
+}
+
+fn csv_field(v: &str) -> String {
+    if v.contains([',', '"', '\n']) {
+        format!("\"{}\"", v.replace('"', "\"\""))
+    } else {
+        v.to_string()
+    }
+}
@@ -0,0 +1,197 @@
+use long_detector_core::builtin::{builtin_detector, DetectConfig};
+use long_detector_core::eval::{
+    render_curves, roc_auc, sweep, threshold_for_precision, Confusion, Sample,
+};
+use long_detector_core::files::collect_files;
+use long_detector_core::{decode_bytes, Model};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "用法: long-eval [选项] <标注文件夹>
+
+标注文件夹下放 positive（应该命中）和 negative（不应命中）两个子文件夹。
+
+选项:
+  -d, --detector <long|nailong>      使用的检测器，默认 long
+  -c, --config-dir <目录>            从目录读取检测器配置
+  -t, --threshold <分数>             评估的阈值，默认用配置里的 trigger
+  -p, --target-precision <精确率>    找出满足该精确率的阈值，默认 0.95
+      --curve <文件>                 把 ROC 和 PR 曲线画成 PNG
+  -h, --help                         显示帮助";
+
+struct Args {
+    detector: String,
+    config_dir: Option<PathBuf>,
+    threshold: Option<f32>,
+    target_precision: f32,
+    curve_path: Option<PathBuf>,
+    dataset: PathBuf,
+}
+
+fn main() -> ExitCode {
+    if std::env::args().any(|v| v == "-h" || v == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let (model, labels, default_config) = builtin_detector(&args.detector).unwrap();
+    let config = match &args.config_dir {
+        Some(dir) => {
+            let path = dir.join(format!("{}_config.json", args.detector));
+            match DetectConfig::from_file_or(&path, default_config) {
+                Ok(v) => v,
+                Err(err) => {
+                    eprintln!("读取配置失败: {}", err);
+                    return ExitCode::from(2);
+                }
+            }
+        }
+        None => default_config,
+    };
+    let thresholds = config.thresholds(labels);
+    let model = match Model::new(model, labels.to_vec()) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("加载模型失败: {}", err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut samples = Vec::new();
+    for (dir, positive) in [("positive", true), ("negative", false)] {
+        // 文件夹不存在时下面会提示没有图片
+        let files = collect_files(&args.dataset.join(dir), |_, _| {});
+        if files.is_empty() {
+            eprintln!("{} 下没有图片", args.dataset.join(dir).display());
+            return ExitCode::from(2);
+        }
+
+        for file in files {
+            let img = match std::fs::read(&file)
+                .map_err(Box::<dyn std::error::Error>::from)
+                .and_then(|data| decode_bytes(&data))
+            {
+                Ok(v) => v,
+                Err(err) => {
+                    eprintln!("{}: {}", file.display(), err);
+                    continue;
+                }
+            };
+
+            match model.detect(&img, &thresholds) {
+                Ok(detection) => samples.push(Sample {
+                    score: detection.max_target_score,
+                    positive,
+                }),
+                Err(err) => eprintln!("{}: 推理失败: {}", file.display(), err),
+            }
+        }
+    }
+
+    let positives = samples.iter().filter(|v| v.positive).count();
+    println!(
+        "样本数: {}（正样本 {}，负样本 {}）",
+        samples.len(),
+        positives,
+        samples.len() - positives
+    );
+
+    // 类别单独配置的阈值不参与评估，只看 trigger
+    let threshold = args.threshold.unwrap_or(thresholds.trigger);
+    print_confusion("当前阈值", &Confusion::at(&samples, threshold));
+
+    let curve = sweep(&samples);
+    println!("ROC AUC: {:.4}", roc_auc(&curve));
+    match threshold_for_precision(&curve, args.target_precision) {
+        Some(v) => print_confusion(&format!("精确率 ≥ {:.2}", args.target_precision), &v),
+        None => println!("没有阈值能达到精确率 {:.2}", args.target_precision),
+    }
+
+    println!("\n曲线点:");
+    println!("threshold,precision,recall,fpr,f1");
+    for v in &curve {
+        println!(
+            "{:.4},{:.4},{:.4},{:.4},{:.4}",
+            v.threshold,
+            v.precision(),
+            v.recall(),
+            v.false_positive_rate(),
+            v.f1()
+        );
+    }
+
+    if let Some(path) = &args.curve_path {
+        if let Err(err) = render_curves(&curve).save(path) {
+            eprintln!("保存曲线失败: {}", err);
+            return ExitCode::from(2);
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn print_confusion(title: &str, v: &Confusion) {
+    println!(
+        "{}（阈值 {:.4}）: precision {:.4}  recall {:.4}  F1 {:.4}  TP {} FP {} TN {} FN {}",
+        title,
+        v.threshold,
+        v.precision(),
+        v.recall(),
+        v.f1(),
+        v.tp,
+        v.fp,
+        v.tn,
+        v.fn_
+    );
+}
+
+fn parse_args(mut iter: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut detector = "long".to_string();
+    let mut config_dir = None;
+    let mut threshold = None;
+    let mut target_precision = 0.95;
+    let mut curve_path = None;
+    let mut dataset = None;
+
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| iter.next().ok_or(format!("{} 缺少参数", name));
+        match arg.as_str() {
+            "-d" | "--detector" => {
+                detector = value(&arg)?;
+                if builtin_detector(&detector).is_none() {
+                    return Err(format!("未知的检测器: {}", detector));
+                }
+            }
+            "-c" | "--config-dir" => config_dir = Some(PathBuf::from(value(&arg)?)),
+            "-t" | "--threshold" => threshold = Some(parse_score(&value(&arg)?)?),
+            "-p" | "--target-precision" => target_precision = parse_score(&value(&arg)?)?,
+            "--curve" => curve_path = Some(PathBuf::from(value(&arg)?)),
+            v if v.starts_with('-') => return Err(format!("未知的选项: {}", v)),
+            _ => dataset = Some(PathBuf::from(&arg)),
+        }
+    }
+
+    Ok(Args {
+        detector,
+        config_dir,
+        threshold,
+        target_precision,
+        curve_path,
+        dataset: dataset.ok_or("没有指定标注文件夹")?,
+    })
+}
+
+fn parse_score(v: &str) -> Result<f32, String> {
+    match v.parse::<f32>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(v),
+        _ => Err(format!("分数应在 0 到 1 之间: {}", v)),
+    }
+}
@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "用法: long-migrate <data 文件夹> [数据库文件]
+
+把 data 文件夹里的白名单和用户信息导入 SQLite，数据库默认为 <data 文件夹>/data.db。";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|v| v == "-h" || v == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let (data_dir, db_path) = match args.as_slice() {
+        [data_dir] => (
+            PathBuf::from(data_dir),
+            PathBuf::from(data_dir).join("data.db"),
+        ),
+        [data_dir, db_path] => (PathBuf::from(data_dir), PathBuf::from(db_path)),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match check_alllong::sqlite::migrate_json(&data_dir, &db_path) {
+        Ok(report) => {
+            println!(
+                "已导入 {} 条白名单，{} 条用户记录，{} 条命中记录到 {}",
+                report.whitelist,
+                report.user_counts,
+                report.detections,
+                db_path.display()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("导入失败: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}